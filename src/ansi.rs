@@ -0,0 +1,346 @@
+// 将 yas 输出中的 ANSI SGR 转义序列（`ESC [ ... m`）解析为带样式的片段，
+// 其它控制序列直接丢弃。
+
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::{Serialize, Serializer};
+use std::{borrow::Cow, fmt::Write};
+
+// 序列化为 CSS 颜色，如 `#cd3131`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    // 0-255，其中 0-15 为标准色
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    const BASIC: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 49, 49),
+        (13, 188, 121),
+        (229, 229, 16),
+        (36, 114, 200),
+        (188, 63, 188),
+        (17, 168, 205),
+        (229, 229, 229),
+        (102, 102, 102),
+        (241, 76, 76),
+        (35, 209, 139),
+        (245, 245, 67),
+        (59, 142, 234),
+        (214, 112, 214),
+        (41, 184, 219),
+        (255, 255, 255),
+    ];
+
+    pub fn to_rgb(self) -> (u8, u8, u8) {
+        match self {
+            Color::Rgb(r, g, b) => (r, g, b),
+            Color::Indexed(n @ 0..=15) => Self::BASIC[n as usize],
+            Color::Indexed(n @ 16..=231) => {
+                let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+                let n = n - 16;
+                (level(n / 36), level(n / 6 % 6), level(n % 6))
+            },
+            Color::Indexed(n) => {
+                let v = 8 + (n - 232) * 10;
+                (v, v, v)
+            },
+        }
+    }
+
+    pub fn to_css(self) -> String {
+        let (r, g, b) = self.to_rgb();
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_css())
+    }
+}
+
+impl JsonSchema for Color {
+    fn schema_name() -> Cow<'static, str> {
+        "Color".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "string",
+            "description": "CSS color, e.g. `#cd3131`"
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Style {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fg: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bg: Option<Color>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub bold: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub italic: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub underline: bool,
+}
+
+impl Style {
+    // `params` 为以分号分隔的各组参数，组内以冒号分隔子参数，空的参数为 `None`
+    fn apply(&mut self, params: &[Vec<Option<u32>>]) {
+        let mut iter = params.iter();
+        while let Some(group) = iter.next() {
+            let param = group[0].unwrap_or(0);
+            match param {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => self.bold = false,
+                23 => self.italic = false,
+                24 => self.underline = false,
+                30..=37 => self.fg = Some(Color::Indexed((param - 30) as u8)),
+                39 => self.fg = None,
+                40..=47 => self.bg = Some(Color::Indexed((param - 40) as u8)),
+                49 => self.bg = None,
+                90..=97 => self.fg = Some(Color::Indexed((param - 90 + 8) as u8)),
+                100..=107 => self.bg = Some(Color::Indexed((param - 100 + 8) as u8)),
+                38 | 48 => {
+                    let color = if group.len() > 1 {
+                        // 冒号形式 `38:5:n`、`38:2:[色彩空间]:r:g:b`，颜色在同一组内，色彩空间可以省略
+                        let kind = group[1];
+                        let values = match kind {
+                            Some(2) if group.len() > 5 => &group[3..],
+                            _ => &group[2..],
+                        };
+                        extended_color(kind, values)
+                    } else {
+                        // 分号形式 `38;5;n`、`38;2;r;g;b`，颜色占用其后的几组参数
+                        let kind = iter.next().and_then(|group| group[0]);
+                        let count = match kind {
+                            Some(5) => 1,
+                            Some(2) => 3,
+                            _ => 0,
+                        };
+                        let values: Vec<_> = iter.by_ref().take(count).map(|group| group[0]).collect();
+                        extended_color(kind, &values)
+                    };
+                    if param == 38 {
+                        self.fg = color;
+                    } else {
+                        self.bg = color;
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn to_css(self) -> String {
+        let mut css = String::new();
+        if let Some(fg) = self.fg {
+            let _ = write!(css, "color:{};", fg.to_css());
+        }
+        if let Some(bg) = self.bg {
+            let _ = write!(css, "background-color:{};", bg.to_css());
+        }
+        if self.bold {
+            css.push_str("font-weight:bold;");
+        }
+        if self.italic {
+            css.push_str("font-style:italic;");
+        }
+        if self.underline {
+            css.push_str("text-decoration:underline;");
+        }
+        css
+    }
+}
+
+// 256 色的序号或真彩色的分量，参数不足时为 `None`。
+// 真彩色超出 255 的分量按 255 处理，超出 255 的序号忽略
+fn extended_color(kind: Option<u32>, values: &[Option<u32>]) -> Option<Color> {
+    let value = |i: usize| values.get(i).map(|value| value.unwrap_or(0));
+    match kind {
+        Some(5) => value(0).and_then(|n| u8::try_from(n).ok()).map(Color::Indexed),
+        Some(2) => {
+            let channel = |i: usize| value(i).map(|value| value.min(255) as u8);
+            Some(Color::Rgb(channel(0)?, channel(1)?, channel(2)?))
+        },
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Span {
+    pub text: String,
+    #[serde(flatten)]
+    pub style: Style,
+}
+
+pub fn contains_escape(text: &str) -> bool {
+    text.contains('\x1b')
+}
+
+pub fn parse(text: &str) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    let mut style = Style::default();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            match spans.last_mut() {
+                Some(last) if last.style == style => last.text.push(c),
+                _ => spans.push(Span {
+                    text: c.to_string(),
+                    style,
+                }),
+            }
+            continue;
+        }
+        if chars.peek() != Some(&'[') {
+            // 非 CSI 序列，丢弃下一个字符
+            chars.next();
+            continue;
+        }
+        chars.next();
+        let mut body = String::new();
+        let mut end = None;
+        for c in chars.by_ref() {
+            if ('\x40'..='\x7e').contains(&c) {
+                end = Some(c);
+                break;
+            }
+            body.push(c);
+        }
+        if end == Some('m') {
+            // 没有参数的 `ESC [ m` 与 `ESC [ 0 m` 相同
+            let params: Vec<Vec<Option<u32>>> = body
+                .split(';')
+                .map(|group| group.split(':').map(|param| param.parse().ok()).collect())
+                .collect();
+            style.apply(&params);
+        }
+    }
+    spans
+}
+
+pub fn to_plain(spans: &[Span]) -> String {
+    spans.iter().map(|span| span.text.as_str()).collect()
+}
+
+pub fn to_html(spans: &[Span]) -> String {
+    let mut html = String::new();
+    for span in spans {
+        let text = escape_html(&span.text);
+        if span.style == Style::default() {
+            html.push_str(&text);
+        } else {
+            let _ = write!(html, "<span style=\"{}\">{text}</span>", span.style.to_css());
+        }
+    }
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(text: &str) -> Style {
+        parse(&format!("{text}x")).last().unwrap().style
+    }
+
+    fn fg(color: Color) -> Style {
+        Style {
+            fg: Some(color),
+            ..Style::default()
+        }
+    }
+
+    #[test]
+    fn splits_text_into_styled_spans() {
+        let spans = parse("\x1b[1;31mERROR\x1b[0m done\x1b[K");
+        assert_eq!(
+            spans,
+            vec![
+                Span {
+                    text: "ERROR".to_owned(),
+                    style: Style {
+                        bold: true,
+                        ..fg(Color::Indexed(1))
+                    },
+                },
+                Span {
+                    text: " done".to_owned(),
+                    style: Style::default(),
+                },
+            ]
+        );
+        assert_eq!(to_plain(&spans), "ERROR done");
+        assert_eq!(
+            to_html(&spans),
+            "<span style=\"color:#cd3131;font-weight:bold;\">ERROR</span> done"
+        );
+    }
+
+    #[test]
+    fn resets_and_toggles_attributes() {
+        assert_eq!(style("\x1b[1;4;3m\x1b[m"), Style::default());
+        assert_eq!(style("\x1b[1;31m\x1b[0m"), Style::default());
+        assert_eq!(style("\x1b[1;31m\x1b[22m"), fg(Color::Indexed(1)));
+        assert!(style("\x1b[31;;1m").bold);
+        assert_eq!(style("\x1b[31;;1m").fg, None);
+    }
+
+    #[test]
+    fn parses_16_and_256_colors() {
+        assert_eq!(style("\x1b[32m"), fg(Color::Indexed(2)));
+        assert_eq!(style("\x1b[92m"), fg(Color::Indexed(10)));
+        assert_eq!(style("\x1b[44m").bg, Some(Color::Indexed(4)));
+        assert_eq!(style("\x1b[104m").bg, Some(Color::Indexed(12)));
+        assert_eq!(style("\x1b[38;5;196m"), fg(Color::Indexed(196)));
+        assert_eq!(style("\x1b[38:5:196m"), fg(Color::Indexed(196)));
+        assert_eq!(style("\x1b[48;5;232m").bg, Some(Color::Indexed(232)));
+        assert_eq!(style("\x1b[38;5;300m").fg, None);
+        assert_eq!(Color::Indexed(196).to_css(), "#ff0000");
+        assert_eq!(Color::Indexed(232).to_css(), "#080808");
+    }
+
+    #[test]
+    fn parses_truecolor_with_both_separators() {
+        let orange = fg(Color::Rgb(255, 128, 0));
+        assert_eq!(style("\x1b[38;2;255;128;0m"), orange);
+        assert_eq!(style("\x1b[38:2::255:128:0m"), orange);
+        assert_eq!(style("\x1b[38:2:0:255:128:0m"), orange);
+        assert_eq!(style("\x1b[38:2:255:128:0m"), orange);
+        assert_eq!(style("\x1b[48;2;1;2;3m").bg, Some(Color::Rgb(1, 2, 3)));
+        // 颜色之后的参数照常处理
+        assert!(style("\x1b[38;2;255;128;0;1m").bold);
+        assert!(style("\x1b[38:2::255:128:0;1m").bold);
+    }
+
+    #[test]
+    fn clamps_out_of_range_truecolor() {
+        assert_eq!(style("\x1b[38;2;300;128;1000m"), fg(Color::Rgb(255, 128, 255)));
+        assert_eq!(style("\x1b[38:2::256:0:0m"), fg(Color::Rgb(255, 0, 0)));
+        assert_eq!(style("\x1b[38;2;1;2m").fg, None);
+    }
+}
@@ -0,0 +1,216 @@
+// asciicast v2 格式的终端录像：https://docs.asciinema.org/manual/asciicast/v2/
+// 第一行为 header，之后每行是一个 `[时间, "o", 输出]` 事件。
+
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+use tokio::{fs::File, io::AsyncWriteExt, sync::mpsc};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Header {
+    pub version: u32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Event(pub f64, pub String, pub String);
+
+impl Event {
+    pub fn time(&self) -> Duration {
+        Duration::from_secs_f64(self.0.max(0.0))
+    }
+
+    pub fn is_output(&self) -> bool {
+        self.1 == "o"
+    }
+}
+
+pub struct Recorder {
+    file: File,
+    start: Instant,
+}
+
+impl Recorder {
+    pub async fn create(path: &Path, command: &str) -> io::Result<Recorder> {
+        let mut file = File::create(path).await?;
+        let header = Header {
+            version: 2,
+            width: 120,
+            height: 40,
+            timestamp: Some(chrono::Utc::now().timestamp()),
+            command: Some(command.to_owned()),
+            title: None,
+        };
+        file.write_all(format!("{}\n", serde_json::to_string(&header)?).as_bytes())
+            .await?;
+        Ok(Recorder {
+            file,
+            start: Instant::now(),
+        })
+    }
+
+    // yas 按行输出，录像中补上终端的换行
+    pub async fn record_line(&mut self, line: &str) -> io::Result<()> {
+        let event = Event(
+            self.start.elapsed().as_secs_f64(),
+            "o".to_owned(),
+            format!("{line}\r\n"),
+        );
+        self.file
+            .write_all(format!("{}\n", serde_json::to_string(&event)?).as_bytes())
+            .await
+    }
+
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.file.flush().await
+    }
+}
+
+pub fn read(path: &Path) -> Result<(Header, Vec<Event>), Box<dyn Error>> {
+    let mut lines = BufReader::new(fs::File::open(path)?).lines();
+    let header: Header = serde_json::from_str(&lines.next().ok_or("empty asciicast file")??)?;
+    if header.version != 2 {
+        return Err(format!("unsupported asciicast version {}", header.version).into());
+    }
+    let mut events = Vec::new();
+    for line in lines {
+        let line = line?;
+        if !line.trim().is_empty() {
+            events.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok((header, events))
+}
+
+// 按录像中的时间间隔在终端回放，`speed` 为倍速
+pub async fn play_in_terminal(events: &[Event], speed: f64) -> io::Result<()> {
+    let start = tokio::time::Instant::now();
+    let mut stdout = io::stdout();
+    for event in events.iter().filter(|event| event.is_output()) {
+        tokio::time::sleep_until(start + event.time().div_f64(speed)).await;
+        stdout.write_all(event.2.as_bytes())?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+// 按录像中的时间间隔逐行发送输出，用于在没有游戏的情况下模拟一次 yas 运行
+pub async fn play_lines(events: &[Event], speed: f64, output: mpsc::Sender<String>) {
+    let start = tokio::time::Instant::now();
+    let mut pending = String::new();
+    for event in events.iter().filter(|event| event.is_output()) {
+        tokio::time::sleep_until(start + event.time().div_f64(speed)).await;
+        pending.push_str(&event.2);
+        while let Some(pos) = pending.find('\n') {
+            let line = pending[..pos].trim_end_matches('\r').to_owned();
+            pending.drain(..=pos);
+            if output.send(line).await.is_err() {
+                return;
+            }
+        }
+    }
+    if !pending.is_empty() {
+        let _ = output.send(pending).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn temp_file() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("frostflake-cast-{}.cast", Uuid::new_v4()))
+    }
+
+    fn event(time: f64, output: &str) -> Event {
+        Event(time, "o".to_owned(), output.to_owned())
+    }
+
+    #[tokio::test]
+    async fn recorded_casts_read_back() {
+        let path = temp_file();
+        let mut recorder = Recorder::create(&path, "yas --dump").await.unwrap();
+        recorder.record_line("[INFO] yas version 0.1.17").await.unwrap();
+        recorder.record_line("原神").await.unwrap();
+        recorder.flush().await.unwrap();
+        drop(recorder);
+
+        let (header, events) = read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(header.version, 2);
+        assert_eq!(header.command.as_deref(), Some("yas --dump"));
+        assert!(header.timestamp.is_some());
+        let outputs: Vec<_> = events.iter().map(|event| event.2.as_str()).collect();
+        assert_eq!(outputs, ["[INFO] yas version 0.1.17\r\n", "原神\r\n"]);
+        assert!(events.iter().all(Event::is_output));
+        assert!(events[0].0 <= events[1].0);
+    }
+
+    #[test]
+    fn rejects_malformed_casts() {
+        let header = r#"{"version": 2, "width": 80, "height": 24}"#;
+        for text in [
+            String::new(),
+            r#"{"version": 1, "width": 80, "height": 24}"#.to_owned(),
+            format!("{header}\n[0.5, \"o\"]\n"),
+            format!("{header}\nnot json\n"),
+        ] {
+            let path = temp_file();
+            fs::write(&path, &text).unwrap();
+            let result = read(&path);
+            let _ = fs::remove_file(&path);
+            assert!(result.is_err(), "accepted {text:?}");
+        }
+        // 空行被忽略，其他类型的事件照常读取
+        let path = temp_file();
+        fs::write(&path, format!("{header}\n\n[0.5, \"i\", \"q\"]\n")).unwrap();
+        let (_, events) = read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(events.len(), 1);
+        assert!(!events[0].is_output());
+    }
+
+    #[tokio::test]
+    async fn play_lines_joins_chunks_into_lines() {
+        let events = [
+            event(0.0, "a\r\nb"),
+            Event(0.0, "i".to_owned(), "ignored\n".to_owned()),
+            event(0.0, "c\r\n"),
+            event(0.0, "tail"),
+        ];
+        let (tx, mut rx) = mpsc::channel(8);
+        play_lines(&events, 1.0, tx).await;
+        let mut lines = Vec::new();
+        while let Some(line) = rx.recv().await {
+            lines.push(line);
+        }
+        assert_eq!(lines, ["a", "bc", "tail"]);
+    }
+
+    #[tokio::test]
+    async fn playback_speed_scales_the_delays() {
+        let events = [event(1.0, "a\n"), event(2.0, "b\n")];
+        let (tx, mut rx) = mpsc::channel(8);
+        let start = Instant::now();
+        play_lines(&events, 10.0, tx).await;
+        let elapsed = start.elapsed();
+        // 10 倍速下 2 秒的录像约 0.2 秒放完
+        assert!(elapsed >= Duration::from_millis(200), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+        assert_eq!(rx.recv().await.as_deref(), Some("a"));
+        assert_eq!(rx.recv().await.as_deref(), Some("b"));
+    }
+}
@@ -0,0 +1,117 @@
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{header::AUTHORIZATION, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    // 运行 yas、读取扫描记录
+    Scan,
+    // 列出、切换窗口
+    Windows,
+    // 模拟鼠标与键盘输入，需要在申请令牌时单独请求
+    Input,
+}
+
+impl Scope {
+    // 用户在控制台同意授权后获得的权限
+    pub const DEFAULT: [Scope; 2] = [Scope::Scan, Scope::Windows];
+    // 默认权限以外、可以在申请令牌时请求的权限
+    pub const OPTIONAL: [Scope; 1] = [Scope::Input];
+}
+
+// 一个令牌及其来源与权限
+#[derive(Debug, Clone, Serialize)]
+pub struct Grant {
+    pub token: Uuid,
+    pub origin: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl Grant {
+    pub fn has(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    pub fn require(&self, scope: Scope) -> Result<(), Forbidden> {
+        if self.has(scope) {
+            Ok(())
+        } else {
+            Err(Forbidden(scope))
+        }
+    }
+}
+
+// 令牌缺少所需的权限
+#[derive(Debug)]
+pub struct Forbidden(pub Scope);
+
+impl IntoResponse for Forbidden {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::FORBIDDEN,
+            Json(json!({"msg": "forbidden", "scope": self.0})),
+        )
+            .into_response()
+    }
+}
+
+// 可以廉价地克隆，克隆之间共享同一份令牌
+#[derive(Clone, Default)]
+pub struct TokenStore {
+    grants: Arc<Mutex<HashMap<Uuid, Grant>>>,
+}
+
+impl TokenStore {
+    pub fn insert(&self, grant: Grant) {
+        self.grants.lock().unwrap().insert(grant.token, grant);
+    }
+
+    pub fn get(&self, token: &Uuid) -> Option<Grant> {
+        self.grants.lock().unwrap().get(token).cloned()
+    }
+}
+
+fn unauthorized() -> Response {
+    (StatusCode::UNAUTHORIZED, Json(json!({"msg": "unauthorized"}))).into_response()
+}
+
+// 从 `Authorization: Bearer <token>` 或 `?token=<token>`（EventSource 无法设置请求头）中读取令牌。
+// WebSocket 转发的请求直接在 extensions 中带有该连接的 `Grant`。
+impl<S> FromRequestParts<S> for Grant
+where
+    S: Send + Sync,
+    TokenStore: FromRef<S>,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(grant) = parts.extensions.get::<Grant>() {
+            return Ok(grant.clone());
+        }
+        let from_header = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let from_query = parts
+            .uri
+            .query()
+            .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("token=")));
+        let token = from_header
+            .or(from_query)
+            .and_then(|token| Uuid::parse_str(token.trim()).ok())
+            .ok_or_else(unauthorized)?;
+        TokenStore::from_ref(state).get(&token).ok_or_else(unauthorized)
+    }
+}
@@ -0,0 +1,332 @@
+// 窗口截图：`GET /api/windows/{hwnd}/capture` 返回一张 PNG 或 JPEG，
+// `GET /api/windows/{hwnd}/capture/stream` 升级为 WebSocket 后按设定的帧率持续发送，每帧为一条二进制消息。
+
+use crate::{
+    codec::{encode_jpeg, encode_png},
+    platform::{Capture, Frame, Rect, WindowInfo, WindowManager},
+};
+
+use axum::extract::ws::{Message, WebSocket};
+use serde::Deserialize;
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+
+// 帧率的上限，GDI 截取 4K 画面并编码大约需要几十毫秒
+pub const MAX_FPS: f64 = 30.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+// 截图的查询参数
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CaptureOptions {
+    pub format: ImageFormat,
+    // JPEG 的质量，1 到 100
+    pub quality: u8,
+    // 裁剪区域，相对于客户区的左上角，缺省时到客户区的右边与下边为止
+    pub x: i32,
+    pub y: i32,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    // 裁剪后的缩放比例，只能缩小
+    pub scale: f64,
+    // 仅用于 WebSocket
+    pub fps: f64,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        CaptureOptions {
+            format: ImageFormat::Png,
+            quality: 80,
+            x: 0,
+            y: 0,
+            width: None,
+            height: None,
+            scale: 1.0,
+            fps: 5.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    // 参数不合法，对应 400
+    Invalid(String),
+    // 窗口不存在或已关闭，对应 404
+    NotFound,
+    // 窗口已最小化，没有可截取的画面，对应 409
+    Minimized,
+    // 截图失败，对应 500
+    Failed(std::io::Error),
+}
+
+impl std::fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureError::Invalid(msg) => write!(f, "{msg}"),
+            CaptureError::NotFound => write!(f, "window not found"),
+            CaptureError::Minimized => write!(f, "window is minimized"),
+            CaptureError::Failed(err) => write!(f, "capture failed: {err}"),
+        }
+    }
+}
+
+impl CaptureOptions {
+    pub fn validate(&self) -> Result<(), CaptureError> {
+        if !(1..=100).contains(&self.quality) {
+            return Err(CaptureError::Invalid("quality must be between 1 and 100".to_owned()));
+        }
+        if !(self.scale > 0.0 && self.scale <= 1.0) {
+            return Err(CaptureError::Invalid("scale must be in (0, 1]".to_owned()));
+        }
+        if !(self.fps > 0.0 && self.fps <= MAX_FPS) {
+            return Err(CaptureError::Invalid(format!("fps must be in (0, {MAX_FPS}]")));
+        }
+        Ok(())
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.fps)
+    }
+
+    // 裁剪区域在屏幕上的位置，必须在客户区之内
+    pub fn region(&self, client: Rect) -> Result<Rect, CaptureError> {
+        let width = self.width.unwrap_or(client.width - self.x);
+        let height = self.height.unwrap_or(client.height - self.y);
+        let inside = self.x >= 0
+            && self.y >= 0
+            && width > 0
+            && height > 0
+            && self.x + width <= client.width
+            && self.y + height <= client.height;
+        if !inside {
+            return Err(CaptureError::Invalid(format!(
+                "region {}×{} at ({}, {}) is outside the {}×{} client area",
+                width, height, self.x, self.y, client.width, client.height
+            )));
+        }
+        Ok(Rect {
+            x: client.x + self.x,
+            y: client.y + self.y,
+            width,
+            height,
+        })
+    }
+}
+
+// 截取窗口并按参数裁剪、缩放、编码
+pub fn snapshot(capture: &dyn Capture, window: &WindowInfo, options: &CaptureOptions) -> Result<Vec<u8>, CaptureError> {
+    if window.minimized {
+        return Err(CaptureError::Minimized);
+    }
+    let region = options.region(window.client)?;
+    let frame = capture.capture_screen(region).map_err(CaptureError::Failed)?;
+    let frame = scale(frame, options.scale);
+    Ok(match options.format {
+        ImageFormat::Png => encode_png(&frame),
+        ImageFormat::Jpeg => encode_jpeg(&frame, options.quality),
+    })
+}
+
+// 以区域平均缩小，`factor` 为 1 时原样返回
+pub fn scale(frame: Frame, factor: f64) -> Frame {
+    let width = ((frame.width as f64 * factor).round() as u32).max(1);
+    let height = ((frame.height as f64 * factor).round() as u32).max(1);
+    if (width, height) == (frame.width, frame.height) {
+        return frame;
+    }
+    let source = |dst: u32, dst_size: u32, src_size: u32| {
+        let start = (dst as u64 * src_size as u64 / dst_size as u64) as usize;
+        let end = ((dst as u64 + 1) * src_size as u64 / dst_size as u64) as usize;
+        start..end.max(start + 1)
+    };
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 3);
+    for y in 0..height {
+        let rows = source(y, height, frame.height);
+        for x in 0..width {
+            let columns = source(x, width, frame.width);
+            let mut sum = [0u32; 3];
+            for row in rows.clone() {
+                for column in columns.clone() {
+                    let offset = (row * frame.width as usize + column) * 3;
+                    for (c, total) in sum.iter_mut().enumerate() {
+                        *total += frame.pixels[offset + c] as u32;
+                    }
+                }
+            }
+            let count = (rows.len() * columns.len()) as u32;
+            pixels.extend(sum.map(|total| ((total + count / 2) / count) as u8));
+        }
+    }
+    Frame { width, height, pixels }
+}
+
+// 按帧率持续截图，直到客户端断开或窗口关闭。客户端接收不及时会少发帧，而不会积压。
+// 窗口最小化时暂停发送，出错时先发送一条 `{"msg": ...}` 文本消息再关闭连接。
+pub async fn stream(
+    mut socket: WebSocket,
+    windows: Arc<dyn WindowManager>,
+    capture: Arc<dyn Capture>,
+    hwnd: usize,
+    options: CaptureOptions,
+) {
+    let mut ticker = tokio::time::interval(options.interval());
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let options = Arc::new(options);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {},
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => continue,
+            },
+        }
+        let (windows, capture, options) = (windows.clone(), capture.clone(), options.clone());
+        let result = tokio::task::spawn_blocking(move || {
+            let window = windows
+                .list()
+                .map_err(CaptureError::Failed)?
+                .into_iter()
+                .find(|window| window.hwnd == hwnd)
+                .ok_or(CaptureError::NotFound)?;
+            snapshot(capture.as_ref(), &window, &options)
+        })
+        .await
+        .unwrap();
+        let message = match result {
+            Ok(image) => Message::Binary(image.into()),
+            Err(CaptureError::Minimized) => continue,
+            Err(err) => {
+                let _ = socket
+                    .send(Message::Text(json!({"msg": err.to_string()}).to_string().into()))
+                    .await;
+                let _ = socket.send(Message::Close(None)).await;
+                return;
+            },
+        };
+        if socket.send(message).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{Size, SyntheticCapture};
+    use axum::extract::Query;
+
+    fn client() -> Rect {
+        Rect {
+            x: 100,
+            y: 200,
+            width: 1280,
+            height: 720,
+        }
+    }
+
+    fn options(query: &str) -> CaptureOptions {
+        let uri = format!("/capture?{query}").parse().unwrap();
+        Query::try_from_uri(&uri).unwrap().0
+    }
+
+    #[test]
+    fn region_is_relative_to_client() {
+        let region = options("x=10&y=20").region(client()).unwrap();
+        assert_eq!(
+            region,
+            Rect {
+                x: 110,
+                y: 220,
+                width: 1270,
+                height: 700
+            }
+        );
+        let region = options("x=640&width=640&height=360").region(client()).unwrap();
+        assert_eq!(
+            region,
+            Rect {
+                x: 740,
+                y: 200,
+                width: 640,
+                height: 360
+            }
+        );
+        assert!(options("x=640&width=641").region(client()).is_err());
+        assert!(options("y=-1").region(client()).is_err());
+        assert!(options("scale=1.5").validate().is_err());
+        assert!(options("fps=0").validate().is_err());
+        assert!(options("format=jpeg&quality=90&scale=0.5&fps=10").validate().is_ok());
+    }
+
+    #[test]
+    fn scale_averages_pixels() {
+        let frame = SyntheticCapture
+            .capture_screen(Rect {
+                x: 0,
+                y: 0,
+                width: 4,
+                height: 2,
+            })
+            .unwrap();
+        let scaled = scale(frame, 0.5);
+        assert_eq!((scaled.width, scaled.height), (2, 1));
+        // 红色为 x 的平均值，绿色为 y 的平均值
+        assert_eq!(scaled.pixels, vec![1, 1, 0, 3, 1, 0]);
+    }
+
+    #[test]
+    fn snapshot_encodes_cropped_region() {
+        let mut window = WindowInfo {
+            class_name: "UnityWndClass".to_owned(),
+            title: "原神".to_owned(),
+            hwnd: 1,
+            height: 759,
+            width: 1296,
+            x: 92,
+            y: 169,
+            pid: 1,
+            process: "YuanShen.exe".to_owned(),
+            visible: true,
+            minimized: false,
+            maximized: false,
+            monitor: "main".to_owned(),
+            client: client(),
+            dpi: 96,
+            scale: 1.0,
+            logical_client: Size {
+                width: 1280,
+                height: 720,
+            },
+        };
+        let png = snapshot(&SyntheticCapture, &window, &options("width=64&height=32&scale=0.5")).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[16..24], &[0, 0, 0, 32, 0, 0, 0, 16]);
+
+        let jpeg = snapshot(&SyntheticCapture, &window, &options("format=jpeg")).unwrap();
+        assert_eq!(&jpeg[..2], &[0xff, 0xd8]);
+        assert_eq!(&jpeg[jpeg.len() - 2..], &[0xff, 0xd9]);
+
+        window.minimized = true;
+        assert!(matches!(
+            snapshot(&SyntheticCapture, &window, &CaptureOptions::default()),
+            Err(CaptureError::Minimized)
+        ));
+    }
+}
@@ -0,0 +1,596 @@
+// 截图的编码：PNG 与基线 JPEG。只需要编码 RGB 图像，自己实现以免为此引入图像库。
+// PNG 使用固定哈夫曼表的 deflate，JPEG 使用标准哈夫曼表、不做色度抽样。
+
+use crate::platform::Frame;
+
+pub fn encode_png(frame: &Frame) -> Vec<u8> {
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let stride = width * 3;
+    // 每行选择差值绝对值之和最小的过滤方式
+    let mut filtered = Vec::with_capacity((stride + 1) * height);
+    let zero = vec![0; stride];
+    let mut candidate = vec![0; stride];
+    let mut best = vec![0; stride];
+    for y in 0..height {
+        let row = &frame.pixels[y * stride..(y + 1) * stride];
+        let up = if y == 0 {
+            &zero[..]
+        } else {
+            &frame.pixels[(y - 1) * stride..y * stride]
+        };
+        let mut best_filter = 0;
+        let mut best_cost = u64::MAX;
+        for filter in 0..5u8 {
+            for i in 0..stride {
+                let left = if i >= 3 { row[i - 3] } else { 0 };
+                let up_left = if i >= 3 { up[i - 3] } else { 0 };
+                let predicted = match filter {
+                    0 => 0,
+                    1 => left,
+                    2 => up[i],
+                    3 => ((left as u16 + up[i] as u16) / 2) as u8,
+                    _ => paeth(left, up[i], up_left),
+                };
+                candidate[i] = row[i].wrapping_sub(predicted);
+            }
+            let cost = candidate.iter().map(|&value| (value as i8).unsigned_abs() as u64).sum();
+            if cost < best_cost {
+                best_cost = cost;
+                best_filter = filter;
+                std::mem::swap(&mut best, &mut candidate);
+            }
+        }
+        filtered.push(best_filter);
+        filtered.extend_from_slice(&best);
+    }
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&frame.width.to_be_bytes());
+    header.extend_from_slice(&frame.height.to_be_bytes());
+    // 8 位 RGB，无隔行扫描
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib(&filtered));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// 按 LSB 优先写入比特，deflate 与 JPEG 的顺序相反，JPEG 另有实现
+struct LsbWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl LsbWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // 哈夫曼码需要 MSB 优先写入
+    fn write_code(&mut self, code: u32, count: u32) {
+        self.write(code.reverse_bits() >> (32 - count), count);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+const WINDOW: usize = 32768;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+
+// 单个使用固定哈夫曼表的 deflate 块，匹配时只查找同一哈希最近出现的位置
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut writer = LsbWriter {
+        out: vec![0x78, 0x01],
+        bits: 0,
+        count: 0,
+    };
+    // BFINAL = 1，BTYPE = 01
+    writer.write(0b011, 3);
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let hash = |i: usize| {
+        let value = (data[i] as u32) << 16 | (data[i + 1] as u32) << 8 | data[i + 2] as u32;
+        (value.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    };
+    let mut i = 0;
+    while i < data.len() {
+        let mut length = 0;
+        let mut distance = 0;
+        if i + 3 <= data.len() {
+            let h = hash(i);
+            let candidate = head[h];
+            head[h] = i;
+            if candidate != usize::MAX && i - candidate <= WINDOW {
+                let limit = MAX_MATCH.min(data.len() - i);
+                while length < limit && data[candidate + length] == data[i + length] {
+                    length += 1;
+                }
+                distance = i - candidate;
+            }
+        }
+        if length >= 3 {
+            write_length(&mut writer, length);
+            write_distance(&mut writer, distance);
+            for j in i + 1..(i + length).min(data.len().saturating_sub(2)) {
+                head[hash(j)] = j;
+            }
+            i += length;
+        } else {
+            write_literal(&mut writer, data[i] as u32);
+            i += 1;
+        }
+    }
+    write_literal(&mut writer, 256);
+    let mut out = writer.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn write_literal(writer: &mut LsbWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_length(writer: &mut LsbWriter, length: usize) {
+    let code = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap();
+    write_literal(writer, 257 + code as u32);
+    writer.write((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+}
+
+fn write_distance(writer: &mut LsbWriter, distance: usize) {
+    let code = DISTANCE_BASE
+        .iter()
+        .rposition(|&base| base as usize <= distance)
+        .unwrap();
+    writer.write_code(code as u32, 5);
+    writer.write(
+        (distance - DISTANCE_BASE[code] as usize) as u32,
+        DISTANCE_EXTRA[code] as u32,
+    );
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+// 标准的量化表（ITU T.81 附录 K），按行排列
+const LUMA_QUANT: [u8; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56, 14, 17, 22, 29, 51,
+    87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113, 92, 49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+const CHROMA_QUANT: [u8; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99, 24, 26, 56, 99, 99, 99, 99, 99, 47, 66, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99,
+];
+
+// 之字形顺序中第 i 个系数在块中的位置
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21,
+    28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54,
+    47, 55, 62, 63,
+];
+
+// 标准哈夫曼表：各长度的码字个数与按码字顺序排列的符号
+const DC_LUMA_BITS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const DC_CHROMA_BITS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const AC_LUMA_BITS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+const AC_LUMA_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07, 0x22, 0x71, 0x14,
+    0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0, 0x24, 0x33, 0x62, 0x72, 0x82, 0x09,
+    0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a,
+    0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65,
+    0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88,
+    0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9,
+    0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca,
+    0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea,
+    0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+];
+const AC_CHROMA_BITS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+const AC_CHROMA_VALUES: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71, 0x13, 0x22, 0x32,
+    0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0, 0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16,
+    0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39,
+    0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64,
+    0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86,
+    0x87, 0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8,
+    0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9,
+    0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+];
+
+// 由码字个数与符号构造 `符号 -> (码字, 长度)` 的查找表
+struct HuffmanTable {
+    codes: [(u16, u8); 256],
+}
+
+impl HuffmanTable {
+    fn new(bits: &[u8; 16], values: &[u8]) -> HuffmanTable {
+        let mut codes = [(0, 0); 256];
+        let mut code = 0u16;
+        let mut k = 0;
+        for (length, &count) in bits.iter().enumerate() {
+            for _ in 0..count {
+                codes[values[k] as usize] = (code, length as u8 + 1);
+                code += 1;
+                k += 1;
+            }
+            code <<= 1;
+        }
+        HuffmanTable { codes }
+    }
+}
+
+// MSB 优先写入比特，写出的 0xFF 后补 0x00
+struct MsbWriter {
+    out: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+impl MsbWriter {
+    fn write(&mut self, value: u16, count: u8) {
+        self.bits = self.bits << count | (value as u32 & ((1 << count) - 1));
+        self.count += count as u32;
+        while self.count >= 8 {
+            let byte = (self.bits >> (self.count - 8)) as u8;
+            self.out.push(byte);
+            if byte == 0xff {
+                self.out.push(0);
+            }
+            self.count -= 8;
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.count > 0 {
+            // 剩余的位以 1 填充
+            let padding = 8 - self.count as u8;
+            self.write((1 << padding) - 1, padding);
+        }
+    }
+}
+
+// `quality` 为 1 到 100，与 libjpeg 相同的缩放方式
+pub fn encode_jpeg(frame: &Frame, quality: u8) -> Vec<u8> {
+    let quality = quality.clamp(1, 100) as u32;
+    let scale = if quality < 50 {
+        5000 / quality
+    } else {
+        200 - quality * 2
+    };
+    let quant = |table: &[u8; 64]| table.map(|value| ((value as u32 * scale + 50) / 100).clamp(1, 255) as u8);
+    let tables = [quant(&LUMA_QUANT), quant(&CHROMA_QUANT)];
+
+    let mut jpeg = vec![0xff, 0xd8];
+    // APP0 JFIF
+    jpeg.extend_from_slice(&[0xff, 0xe0, 0, 16, b'J', b'F', b'I', b'F', 0, 1, 1, 0, 0, 1, 0, 1, 0, 0]);
+    for (id, table) in tables.iter().enumerate() {
+        jpeg.extend_from_slice(&[0xff, 0xdb, 0, 67, id as u8]);
+        jpeg.extend(ZIGZAG.iter().map(|&i| table[i]));
+    }
+    // SOF0：三个分量，采样因子均为 1
+    let (width, height) = (frame.width as u16, frame.height as u16);
+    jpeg.extend_from_slice(&[0xff, 0xc0, 0, 17, 8]);
+    jpeg.extend_from_slice(&height.to_be_bytes());
+    jpeg.extend_from_slice(&width.to_be_bytes());
+    jpeg.extend_from_slice(&[3, 1, 0x11, 0, 2, 0x11, 1, 3, 0x11, 1]);
+    for (class_id, bits, values) in [
+        (0x00, &DC_LUMA_BITS, &DC_VALUES[..]),
+        (0x10, &AC_LUMA_BITS, &AC_LUMA_VALUES[..]),
+        (0x01, &DC_CHROMA_BITS, &DC_VALUES[..]),
+        (0x11, &AC_CHROMA_BITS, &AC_CHROMA_VALUES[..]),
+    ] {
+        let length = 2 + 1 + 16 + values.len() as u16;
+        jpeg.extend_from_slice(&[0xff, 0xc4]);
+        jpeg.extend_from_slice(&length.to_be_bytes());
+        jpeg.push(class_id);
+        jpeg.extend_from_slice(bits);
+        jpeg.extend_from_slice(values);
+    }
+    jpeg.extend_from_slice(&[0xff, 0xda, 0, 12, 3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0]);
+
+    let huffman = [
+        (
+            HuffmanTable::new(&DC_LUMA_BITS, &DC_VALUES),
+            HuffmanTable::new(&AC_LUMA_BITS, &AC_LUMA_VALUES),
+        ),
+        (
+            HuffmanTable::new(&DC_CHROMA_BITS, &DC_VALUES),
+            HuffmanTable::new(&AC_CHROMA_BITS, &AC_CHROMA_VALUES),
+        ),
+    ];
+    let cosines = cosine_table();
+    let mut writer = MsbWriter {
+        out: jpeg,
+        bits: 0,
+        count: 0,
+    };
+    let mut previous_dc = [0i32; 3];
+    for block_y in (0..frame.height as usize).step_by(8) {
+        for block_x in (0..frame.width as usize).step_by(8) {
+            // 超出图像的部分重复边缘的像素
+            let pixels: [[f32; 3]; 64] = std::array::from_fn(|i| {
+                let x = (block_x + i % 8).min(frame.width as usize - 1);
+                let y = (block_y + i / 8).min(frame.height as usize - 1);
+                let offset = (y * frame.width as usize + x) * 3;
+                let [r, g, b] = [0, 1, 2].map(|c| frame.pixels[offset + c] as f32);
+                [
+                    0.299 * r + 0.587 * g + 0.114 * b - 128.0,
+                    -0.168_736 * r - 0.331_264 * g + 0.5 * b,
+                    0.5 * r - 0.418_688 * g - 0.081_312 * b,
+                ]
+            });
+            for (component, previous_dc) in previous_dc.iter_mut().enumerate() {
+                let table = (component > 0) as usize;
+                let block = pixels.map(|pixel| pixel[component]);
+                let coefficients = dct(&block, &cosines, &tables[table]);
+                let (dc, ac) = &huffman[table];
+                write_block(&mut writer, &coefficients, previous_dc, dc, ac);
+            }
+        }
+    }
+    writer.flush();
+    let mut jpeg = writer.out;
+    jpeg.extend_from_slice(&[0xff, 0xd9]);
+    jpeg
+}
+
+// `cosines[u][x] = C(u) cos((2x + 1)uπ / 16) / 2`
+fn cosine_table() -> [[f32; 8]; 8] {
+    let mut table = [[0.0; 8]; 8];
+    for (u, row) in table.iter_mut().enumerate() {
+        let c = if u == 0 { std::f32::consts::FRAC_1_SQRT_2 } else { 1.0 };
+        for (x, value) in row.iter_mut().enumerate() {
+            *value = c * ((2 * x + 1) as f32 * u as f32 * std::f32::consts::PI / 16.0).cos() / 2.0;
+        }
+    }
+    table
+}
+
+// 二维 DCT 后量化，按之字形顺序返回
+fn dct(block: &[f32; 64], cosines: &[[f32; 8]; 8], quant: &[u8; 64]) -> [i32; 64] {
+    let mut rows = [0f32; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            rows[y * 8 + u] = (0..8).map(|x| block[y * 8 + x] * cosines[u][x]).sum();
+        }
+    }
+    let mut result = [0; 64];
+    for (k, &i) in ZIGZAG.iter().enumerate() {
+        let (v, u) = (i / 8, i % 8);
+        let value: f32 = (0..8).map(|y| rows[y * 8 + u] * cosines[v][y]).sum();
+        // 基线 JPEG 的交流系数最多 10 位
+        let limit = if k == 0 { 2047 } else { 1023 };
+        result[k] = ((value / quant[i] as f32).round() as i32).clamp(-limit, limit);
+    }
+    result
+}
+
+// 系数的位数与其编码：负数写入 `value - 1` 的低位
+fn magnitude(value: i32) -> (u8, u16) {
+    let size = (32 - value.unsigned_abs().leading_zeros()) as u8;
+    let bits = if value < 0 { value - 1 } else { value };
+    (size, bits as u16)
+}
+
+fn write_block(
+    writer: &mut MsbWriter,
+    coefficients: &[i32; 64],
+    previous_dc: &mut i32,
+    dc: &HuffmanTable,
+    ac: &HuffmanTable,
+) {
+    let (size, bits) = magnitude(coefficients[0] - *previous_dc);
+    *previous_dc = coefficients[0];
+    let (code, length) = dc.codes[size as usize];
+    writer.write(code, length);
+    writer.write(bits, size);
+
+    let mut zeros = 0;
+    for &value in &coefficients[1..] {
+        if value == 0 {
+            zeros += 1;
+            continue;
+        }
+        // 连续 16 个零写作 ZRL
+        while zeros >= 16 {
+            let (code, length) = ac.codes[0xf0];
+            writer.write(code, length);
+            zeros -= 16;
+        }
+        let (size, bits) = magnitude(value);
+        let (code, length) = ac.codes[(zeros << 4 | size) as usize];
+        writer.write(code, length);
+        writer.write(bits, size);
+        zeros = 0;
+    }
+    if zeros > 0 {
+        // EOB
+        let (code, length) = ac.codes[0x00];
+        writer.write(code, length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 3]) -> Frame {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| pixel(x, y))
+            .collect();
+        Frame { width, height, pixels }
+    }
+
+    fn gradient(width: u32, height: u32) -> Frame {
+        frame(width, height, |x, y| {
+            [
+                (x * 255 / width) as u8,
+                (y * 255 / height) as u8,
+                ((x + y) * 255 / (width + height)) as u8,
+            ]
+        })
+    }
+
+    // 伪随机的像素，几乎无法压缩
+    fn noise(width: u32, height: u32) -> Frame {
+        let mut state = 1u32;
+        let pixels = (0..width * height * 3)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        Frame { width, height, pixels }
+    }
+
+    fn decode_png(png: &[u8]) -> Frame {
+        let mut reader = png::Decoder::new(png).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+        assert_eq!(info.bit_depth, png::BitDepth::Eight);
+        pixels.truncate(info.buffer_size());
+        Frame {
+            width: info.width,
+            height: info.height,
+            pixels,
+        }
+    }
+
+    #[test]
+    fn checksums_match_known_vectors() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        // 超过 5552 字节时需要在中途取模
+        assert_eq!(adler32(&[0xff; 100_000]), 0x149a_302c);
+    }
+
+    #[test]
+    fn png_round_trips() {
+        let frames = [
+            gradient(37, 23),
+            noise(64, 48),
+            // 重复的条纹，产生最长的匹配与较远的距离
+            frame(300, 200, |x, _| [(x / 10 % 2 * 255) as u8; 3]),
+            frame(1, 1, |_, _| [1, 2, 3]),
+        ];
+        for frame in frames {
+            assert_eq!(decode_png(&encode_png(&frame)), frame);
+        }
+    }
+
+    #[test]
+    fn jpeg_decodes_within_tolerance() {
+        for (frame, quality, max_mean_error) in [(gradient(37, 23), 95, 2.0), (gradient(64, 64), 50, 4.0)] {
+            let jpeg = encode_jpeg(&frame, quality);
+            let mut decoder = jpeg_decoder::Decoder::new(&jpeg[..]);
+            let pixels = decoder.decode().unwrap();
+            let info = decoder.info().unwrap();
+            assert_eq!(info.pixel_format, jpeg_decoder::PixelFormat::RGB24);
+            assert_eq!((info.width as u32, info.height as u32), (frame.width, frame.height));
+            let total: u64 = pixels
+                .iter()
+                .zip(&frame.pixels)
+                .map(|(&a, &b)| a.abs_diff(b) as u64)
+                .sum();
+            let mean_error = total as f64 / pixels.len() as f64;
+            assert!(
+                mean_error < max_mean_error,
+                "quality {quality}: mean error {mean_error}"
+            );
+        }
+    }
+
+    #[test]
+    fn jpeg_quality_changes_the_size() {
+        let frame = noise(32, 32);
+        assert!(encode_jpeg(&frame, 10).len() < encode_jpeg(&frame, 90).len());
+        // 超出范围的质量按 1 与 100 处理
+        assert_eq!(encode_jpeg(&frame, 0), encode_jpeg(&frame, 1));
+        assert_eq!(encode_jpeg(&frame, 255), encode_jpeg(&frame, 100));
+    }
+}
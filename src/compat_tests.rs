@@ -0,0 +1,303 @@
+// 与原版 frostflake（cocogoat-control）协议的兼容性测试。
+// `tests/fixtures/compat` 下的每个文件记录了莫娜占卜铺发出的一组请求及其期望的响应，
+// HTTP 请求直接交给进程内的 `Router`，WebSocket 则连接到在本地随机端口上运行的同一个 `Router`。
+//
+// 期望值只列出原版发出的字段，本程序新增的字段与消息字段视为允许的扩展，不参与比较。
+// 令牌、版本号、依赖系统的值等每次都可能不同的值使用
+// `$any`、`$string`、`$number`、`$bool`、`$uuid`、`$array`、`$object`，匹配对应类型的任意值。
+// 对象只比较期望中列出的字段，数组逐项比较且长度必须相同。
+// 文件中的 `{token}` 会被替换为测试开始时申请到的令牌，`{version}` 替换为本程序的版本。
+// yas 的运行以同一目录下 `yas.cast` 的回放代替；窗口与系统信息来自非 Windows 平台上固定的空实现，
+// 依赖它们的用例标记为 `stub`，在 Windows 上跳过。
+
+use crate::{
+    config::Config,
+    server::{build_app, Replay},
+};
+
+use axum::{
+    body::{to_bytes, Body},
+    http::Request,
+    Router,
+};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, fs, net::SocketAddr, path::PathBuf, time::Duration};
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+const ORIGIN: &str = "https://mona-uranai.com";
+const FIXTURES: &str = "tests/fixtures/compat";
+
+#[derive(Deserialize)]
+struct Fixture {
+    description: String,
+    // 期望值依赖非 Windows 平台固定的窗口与系统信息
+    #[serde(default)]
+    stub: bool,
+    #[serde(default)]
+    http: Vec<HttpExchange>,
+    websocket: Option<WebSocketSession>,
+}
+
+#[derive(Deserialize)]
+struct HttpExchange {
+    request: HttpRequest,
+    response: HttpResponse,
+}
+
+#[derive(Deserialize)]
+struct HttpRequest {
+    method: String,
+    path: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    // 字符串原样发送，其他值序列化为 JSON
+    body: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct HttpResponse {
+    status: u16,
+    // 缺省时不检查响应体
+    body: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct WebSocketSession {
+    path: String,
+    // `Sec-WebSocket-Protocol` 请求头
+    protocol: Option<String>,
+    exchanges: Vec<WebSocketExchange>,
+}
+
+// 发送一条消息后依次收到的消息，心跳不计在内
+#[derive(Deserialize)]
+struct WebSocketExchange {
+    send: Value,
+    receive: Vec<Value>,
+}
+
+// 每个用例使用独立的数据目录与 `Router`，互不影响
+struct TestServer {
+    app: Router,
+    addr: SocketAddr,
+    data_dir: PathBuf,
+}
+
+impl TestServer {
+    async fn start() -> TestServer {
+        let data_dir = std::env::temp_dir().join(format!("frostflake-compat-{}", Uuid::new_v4()));
+        let config = Config {
+            data_dir: Some(data_dir.clone()),
+            ..Config::default()
+        };
+        // 相对于 crate 目录的路径，yas 的启动提示中原样出现
+        let replay = Replay {
+            file: PathBuf::from(FIXTURES).join("yas.cast"),
+            speed: 1.0,
+        };
+        let app = build_app(config, Some(replay), |_, _| true);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn({
+            let app = app.clone();
+            async move { axum::serve(listener, app).await }
+        });
+        TestServer { app, addr, data_dir }
+    }
+
+    async fn request(&self, request: &HttpRequest) -> (u16, Value) {
+        let mut builder = Request::builder().method(request.method.as_str()).uri(&request.path);
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        let body = match &request.body {
+            None => Body::empty(),
+            Some(Value::String(text)) => Body::from(text.clone()),
+            Some(value) => Body::from(value.to_string()),
+        };
+        let response = self.app.clone().oneshot(builder.body(body).unwrap()).await.unwrap();
+        let status = response.status().as_u16();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        // 不是 JSON 的响应体按字符串比较
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+        (status, body)
+    }
+
+    async fn token(&self) -> String {
+        let request = HttpRequest {
+            method: "POST".to_owned(),
+            path: "/token".to_owned(),
+            headers: BTreeMap::from([("Origin".to_owned(), ORIGIN.to_owned())]),
+            body: None,
+        };
+        let (status, body) = self.request(&request).await;
+        assert_eq!(status, 202, "POST /token failed: {body}");
+        body["token"].as_str().unwrap().to_owned()
+    }
+
+    async fn run(&self, fixture: &Fixture) -> Result<(), String> {
+        for (i, exchange) in fixture.http.iter().enumerate() {
+            let request = &exchange.request;
+            let at = format!("http[{i}] {} {}", request.method, request.path);
+            let (status, body) = self.request(request).await;
+            if status != exchange.response.status {
+                return Err(format!(
+                    "{at}: expected status {}, got {status} ({body})",
+                    exchange.response.status
+                ));
+            }
+            if let Some(expected) = &exchange.response.body {
+                matches(expected, &body, "body").map_err(|err| format!("{at}: {err}"))?;
+            }
+        }
+        if let Some(session) = &fixture.websocket {
+            self.run_websocket(session).await?;
+        }
+        Ok(())
+    }
+
+    async fn run_websocket(&self, session: &WebSocketSession) -> Result<(), String> {
+        let mut request = format!("ws://{}{}", self.addr, session.path)
+            .into_client_request()
+            .unwrap();
+        if let Some(protocol) = &session.protocol {
+            request
+                .headers_mut()
+                .insert("Sec-WebSocket-Protocol", protocol.parse().unwrap());
+        }
+        let (mut socket, _) = tokio_tungstenite::connect_async(request)
+            .await
+            .map_err(|err| format!("websocket {}: {err}", session.path))?;
+        for (i, exchange) in session.exchanges.iter().enumerate() {
+            socket
+                .send(Message::text(exchange.send.to_string()))
+                .await
+                .map_err(|err| format!("websocket[{i}]: {err}"))?;
+            for (j, expected) in exchange.receive.iter().enumerate() {
+                let at = format!("websocket[{i}] receive[{j}]");
+                let message = loop {
+                    let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+                        .await
+                        .map_err(|_| format!("{at}: timed out"))?;
+                    match message {
+                        Some(Ok(Message::Text(text))) => break text,
+                        Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+                        other => return Err(format!("{at}: unexpected {other:?}")),
+                    }
+                };
+                let actual: Value =
+                    serde_json::from_str(&message).map_err(|err| format!("{at}: {err} in {message}"))?;
+                matches(expected, &actual, "message").map_err(|err| format!("{at}: {err}"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.data_dir);
+    }
+}
+
+fn matches(expected: &Value, actual: &Value, path: &str) -> Result<(), String> {
+    match expected {
+        Value::String(pattern) if pattern.starts_with('$') => {
+            let ok = match pattern.as_str() {
+                "$any" => true,
+                "$string" => actual.is_string(),
+                "$number" => actual.is_number(),
+                "$bool" => actual.is_boolean(),
+                "$array" => actual.is_array(),
+                "$object" => actual.is_object(),
+                "$uuid" => actual.as_str().is_some_and(|text| Uuid::parse_str(text).is_ok()),
+                _ => panic!("unknown pattern `{pattern}` at {path}"),
+            };
+            match ok {
+                true => Ok(()),
+                false => Err(format!("{path}: expected {pattern}, got {actual}")),
+            }
+        },
+        Value::Object(expected) => {
+            let Value::Object(actual) = actual else {
+                return Err(format!("{path}: expected an object, got {actual}"));
+            };
+            for (key, value) in expected {
+                let path = format!("{path}.{key}");
+                let Some(actual) = actual.get(key) else {
+                    return Err(format!("{path}: missing"));
+                };
+                matches(value, actual, &path)?;
+            }
+            Ok(())
+        },
+        Value::Array(expected) => {
+            let Value::Array(actual) = actual else {
+                return Err(format!("{path}: expected an array, got {actual}"));
+            };
+            if expected.len() != actual.len() {
+                return Err(format!(
+                    "{path}: expected {} items, got {}",
+                    expected.len(),
+                    actual.len()
+                ));
+            }
+            for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                matches(expected, actual, &format!("{path}[{i}]"))?;
+            }
+            Ok(())
+        },
+        _ if expected == actual => Ok(()),
+        _ => Err(format!("{path}: expected {expected}, got {actual}")),
+    }
+}
+
+#[tokio::test]
+async fn fixtures_match_original_protocol() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(FIXTURES);
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no fixtures in {}", dir.display());
+
+    let mut failures = Vec::new();
+    for path in &paths {
+        let server = TestServer::start().await;
+        let token = server.token().await;
+        let text = fs::read_to_string(path)
+            .unwrap()
+            .replace("{token}", &token)
+            .replace("{version}", env!("CARGO_PKG_VERSION"));
+        let fixture: Fixture = serde_json::from_str(&text).unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+        if fixture.stub && cfg!(windows) {
+            continue;
+        }
+        if let Err(err) = server.run(&fixture).await {
+            let name = path.file_name().unwrap().to_string_lossy();
+            failures.push(format!("{name}（{}）: {err}", fixture.description));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "responses drifted from the fixtures:\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn matcher_patterns() {
+    let actual = serde_json::json!({"token": Uuid::new_v4(), "hwnd": 0, "extra": true, "list": [1, "a"]});
+    assert!(matches(&serde_json::json!({"token": "$uuid", "hwnd": "$number"}), &actual, "").is_ok());
+    assert!(matches(&serde_json::json!({"list": [1, "$string"]}), &actual, "").is_ok());
+    assert!(matches(&serde_json::json!({"list": [1]}), &actual, "").is_err());
+    assert!(matches(&serde_json::json!({"hwnd": "$string"}), &actual, "").is_err());
+    assert!(matches(&serde_json::json!({"missing": "$any"}), &actual, "").is_err());
+}
@@ -0,0 +1,97 @@
+use crate::{
+    game::{known_games, GamePattern},
+    outbox::OverflowPolicy,
+    utils::current_dir_file,
+    window_filter::WindowRule,
+};
+
+use serde::{Deserialize, Serialize};
+use std::{env, fs, path::PathBuf};
+
+// 程序配置，读取自程序目录下的 `frostflake.json`，缺省的字段使用默认值
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    // 扫描记录等数据的存放目录，默认为 `%LOCALAPPDATA%\frostflake-rs`
+    pub data_dir: Option<PathBuf>,
+    // 保留最近多少次扫描的记录
+    pub scan_retention: usize,
+    // 内存中为重新连接的客户端保留多少行 yas 输出
+    pub output_buffer_lines: usize,
+    // 是否将每次扫描的终端输出录制为 asciicast v2 文件（扫描目录下的 `session.cast`）
+    pub record_asciicast: bool,
+    // yas 输出的编码，`auto` 或 `utf-8`、`gbk` 等编码名，无法解码的字节会被替换
+    pub output_encoding: String,
+    // WebSocket 心跳间隔（秒）
+    pub ws_ping_interval_secs: u64,
+    // 超过该时间（秒）未收到客户端的任何消息（包括心跳回应）即断开连接
+    pub ws_idle_timeout_secs: u64,
+    // 客户端单条消息的最大字节数
+    pub ws_max_message_bytes: usize,
+    // 每个连接最多积压多少行尚未发送的 yas 输出
+    pub ws_output_queue_lines: usize,
+    // 积压超出时丢弃最早（`drop-oldest`）还是最新（`drop-newest`）的输出
+    pub ws_output_overflow: OverflowPolicy,
+    // `/api/events` 在内存中保留多少条最近的事件，供重新连接的客户端补齐
+    pub event_history: usize,
+    // 网页端能看到与切换的窗口，默认只有已知的游戏客户端，设置为 `[{}]` 则不做限制
+    pub window_allowlist: Vec<WindowRule>,
+    // 内置以外的游戏窗口规则，优先于内置规则
+    pub game_windows: Vec<GamePattern>,
+    // 检测游戏窗口的间隔（毫秒）
+    pub game_poll_interval_ms: u64,
+    // 模拟输入每秒最多发送多少个事件
+    pub input_max_events_per_second: u32,
+    // 紧急停止模拟输入的热键，再次按下恢复
+    pub input_stop_hotkey: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            data_dir: None,
+            scan_retention: 20,
+            output_buffer_lines: 2000,
+            record_asciicast: false,
+            output_encoding: "auto".to_owned(),
+            ws_ping_interval_secs: 15,
+            ws_idle_timeout_secs: 45,
+            ws_max_message_bytes: 64 * 1024,
+            ws_output_queue_lines: 512,
+            ws_output_overflow: OverflowPolicy::DropOldest,
+            event_history: 1000,
+            window_allowlist: known_games().into_iter().map(|pattern| pattern.rule).collect(),
+            game_windows: Vec::new(),
+            game_poll_interval_ms: 1000,
+            input_max_events_per_second: 50,
+            input_stop_hotkey: "Ctrl+Alt+Q".to_owned(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Config {
+        let path = current_dir_file("frostflake.json");
+        let Ok(file) = fs::File::open(&path) else {
+            return Config::default();
+        };
+        match serde_json::from_reader(file) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("无法解析 {}：{err}，使用默认配置", path.display());
+                Config::default()
+            },
+        }
+    }
+
+    // 程序目录可能位于 `C:\Program Files` 而不可写，因此数据默认放在用户目录下
+    pub fn data_dir(&self) -> PathBuf {
+        if let Some(dir) = &self.data_dir {
+            return dir.clone();
+        }
+        match env::var_os("LOCALAPPDATA") {
+            Some(dir) => PathBuf::from(dir).join("frostflake-rs"),
+            None => current_dir_file("data"),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use encoding_rs::{Encoding, GB18030};
+
+// yas 输出的编码。中文 Windows 控制台默认为 GBK（CP936），
+// 自动模式下每行先尝试 UTF-8，失败时按 GB18030（GBK 的超集）解码。
+#[derive(Debug, Clone, Copy)]
+pub enum OutputEncoding {
+    Auto,
+    Fixed(&'static Encoding),
+}
+
+impl OutputEncoding {
+    // 支持 `auto` 以及 WHATWG 编码标签，如 `utf-8`、`gbk`、`gb18030`、`big5`
+    pub fn from_label(label: &str) -> Option<OutputEncoding> {
+        if label.eq_ignore_ascii_case("auto") {
+            return Some(OutputEncoding::Auto);
+        }
+        Encoding::for_label(label.as_bytes()).map(OutputEncoding::Fixed)
+    }
+
+    // 无法解码的字节替换为 U+FFFD，不会失败
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let encoding = match self {
+            OutputEncoding::Auto => match std::str::from_utf8(bytes) {
+                Ok(text) => return text.to_owned(),
+                Err(_) => GB18030,
+            },
+            OutputEncoding::Fixed(encoding) => encoding,
+        };
+        encoding.decode_without_bom_handling(bytes).0.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 「原神」的 GBK 编码
+    const YUANSHEN_GBK: &[u8] = &[0xd4, 0xad, 0xc9, 0xf1];
+
+    #[test]
+    fn auto_prefers_utf8() {
+        let auto = OutputEncoding::from_label("AUTO").unwrap();
+        assert_eq!(auto.decode("原神 found".as_bytes()), "原神 found");
+        assert_eq!(auto.decode(b"plain ascii"), "plain ascii");
+    }
+
+    #[test]
+    fn auto_falls_back_to_gb18030() {
+        let auto = OutputEncoding::from_label("auto").unwrap();
+        let mut line = b"[INFO] ".to_vec();
+        line.extend_from_slice(YUANSHEN_GBK);
+        assert_eq!(auto.decode(&line), "[INFO] 原神");
+    }
+
+    #[test]
+    fn fixed_encodings_do_not_guess() {
+        let gbk = OutputEncoding::from_label("gbk").unwrap();
+        assert_eq!(gbk.decode(YUANSHEN_GBK), "原神");
+        let utf8 = OutputEncoding::from_label("utf-8").unwrap();
+        assert!(utf8.decode(YUANSHEN_GBK).contains('\u{fffd}'));
+        assert!(OutputEncoding::from_label("no-such-encoding").is_none());
+    }
+}
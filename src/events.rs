@@ -0,0 +1,159 @@
+// 服务端事件总线，供 `GET /api/events`（SSE）使用。
+// 事件与 WebSocket 使用相同的 `ServerMessage`，按发布顺序编号，最近的事件保留在内存中，
+// 客户端重新连接时可通过 `Last-Event-ID` 补齐错过的事件。
+
+use crate::{auth::Scope, protocol::ServerMessage};
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast;
+
+#[derive(Debug)]
+pub struct Event {
+    pub id: u64,
+    // 订阅者需要具有该权限才能收到此事件
+    pub scope: Scope,
+    // `ServerMessage` 的 `action`，作为 SSE 的事件类型
+    pub action: String,
+    pub json: String,
+}
+
+struct BusState {
+    next_id: u64,
+    recent: VecDeque<Arc<Event>>,
+    live: broadcast::Sender<Arc<Event>>,
+}
+
+pub struct EventBus {
+    state: Mutex<BusState>,
+    history: usize,
+}
+
+// 订阅时拿到的内容：`Last-Event-ID` 之后仍保留的事件、因过旧而无法补发的事件数，以及后续事件
+pub struct Backlog {
+    pub events: Vec<Arc<Event>>,
+    pub missed: u64,
+    pub live: broadcast::Receiver<Arc<Event>>,
+}
+
+impl EventBus {
+    pub fn new(history: usize) -> EventBus {
+        let history = history.max(1);
+        EventBus {
+            state: Mutex::new(BusState {
+                next_id: 1,
+                recent: VecDeque::with_capacity(history),
+                live: broadcast::channel(history).0,
+            }),
+            history,
+        }
+    }
+
+    pub fn publish(&self, scope: Scope, message: &ServerMessage) {
+        let value = serde_json::to_value(message).unwrap();
+        let action = value["action"].as_str().unwrap_or_default().to_owned();
+        let mut state = self.state.lock().unwrap();
+        let event = Arc::new(Event {
+            id: state.next_id,
+            scope,
+            action,
+            json: value.to_string(),
+        });
+        state.next_id += 1;
+        if state.recent.len() == self.history {
+            state.recent.pop_front();
+        }
+        state.recent.push_back(event.clone());
+        // 没有订阅者时发送失败，忽略即可
+        let _ = state.live.send(event);
+    }
+
+    // 补发编号大于 `last_event_id` 的事件并订阅后续事件，两者之间不会遗漏
+    pub fn subscribe(&self, last_event_id: Option<u64>) -> Backlog {
+        let state = self.state.lock().unwrap();
+        let (events, missed) = match last_event_id {
+            // 服务端重启后编号从头开始，过大的编号视为没有错过任何事件
+            Some(last) if last < state.next_id => {
+                let events: Vec<_> = state.recent.iter().filter(|event| event.id > last).cloned().collect();
+                let oldest = events.first().map_or(state.next_id, |event| event.id);
+                (events, oldest - last - 1)
+            },
+            _ => (Vec::new(), 0),
+        };
+        Backlog {
+            events,
+            missed,
+            live: state.live.subscribe(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::WindowEvent;
+
+    // 发布 `n` 个事件，编号从 1 开始
+    fn bus(history: usize, n: usize) -> EventBus {
+        let bus = EventBus::new(history);
+        for hwnd in 0..n {
+            let message = ServerMessage::Window {
+                data: Box::new(WindowEvent::Activated { hwnd }),
+            };
+            bus.publish(Scope::Windows, &message);
+        }
+        bus
+    }
+
+    fn ids(backlog: &Backlog) -> Vec<u64> {
+        backlog.events.iter().map(|event| event.id).collect()
+    }
+
+    #[test]
+    fn resumes_after_an_id_in_the_history() {
+        let backlog = bus(4, 6).subscribe(Some(4));
+        assert_eq!(ids(&backlog), [5, 6]);
+        assert_eq!(backlog.missed, 0);
+        assert_eq!(backlog.events[0].action, "window");
+        assert_eq!(backlog.events[0].scope, Scope::Windows);
+        // 已经收到最新的事件
+        let backlog = bus(4, 6).subscribe(Some(6));
+        assert!(backlog.events.is_empty());
+        assert_eq!(backlog.missed, 0);
+    }
+
+    #[test]
+    fn counts_events_older_than_the_history() {
+        // 只保留 3 到 6，1 之后的 2 已被丢弃
+        let backlog = bus(4, 6).subscribe(Some(1));
+        assert_eq!(ids(&backlog), [3, 4, 5, 6]);
+        assert_eq!(backlog.missed, 1);
+        let backlog = bus(4, 6).subscribe(Some(0));
+        assert_eq!(backlog.missed, 2);
+    }
+
+    #[test]
+    fn ignores_unknown_or_future_ids() {
+        // 服务端重启后客户端带来的编号可能比现有的都大
+        let backlog = bus(4, 6).subscribe(Some(100));
+        assert!(backlog.events.is_empty());
+        assert_eq!(backlog.missed, 0);
+        let backlog = bus(4, 6).subscribe(None);
+        assert!(backlog.events.is_empty());
+        assert_eq!(backlog.missed, 0);
+    }
+
+    #[test]
+    fn live_events_follow_the_backlog() {
+        let bus = bus(4, 2);
+        let mut backlog = bus.subscribe(Some(1));
+        assert_eq!(ids(&backlog), [2]);
+        let message = ServerMessage::Window {
+            data: Box::new(WindowEvent::Activated { hwnd: 0 }),
+        };
+        bus.publish(Scope::Windows, &message);
+        assert_eq!(backlog.live.try_recv().unwrap().id, 3);
+    }
+}
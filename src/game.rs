@@ -0,0 +1,339 @@
+// 游戏窗口检测：后台定时枚举窗口，识别已知的游戏客户端，窗口出现、关闭或改变分辨率时发出事件，
+// 网页端不必再轮询 `/api/windows`。
+
+use crate::{
+    auth::Scope,
+    config::Config,
+    events::EventBus,
+    platform::{Rect, Size, WindowInfo, WindowManager},
+    protocol::{ServerMessage, WindowResized},
+    window_filter::WindowRule,
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::broadcast;
+
+// 匹配 `rule` 的窗口视为游戏 `game` 的客户端
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GamePattern {
+    pub game: String,
+    #[serde(flatten)]
+    pub rule: WindowRule,
+}
+
+// 内置的游戏客户端。游戏进程受保护而查不到文件名时，按窗口类名与标题识别
+pub fn known_games() -> Vec<GamePattern> {
+    let process = |game: &str, process: &str| GamePattern {
+        game: game.to_owned(),
+        rule: WindowRule {
+            process: Some(process.to_owned()),
+            ..WindowRule::default()
+        },
+    };
+    let title = |game: &str, title: &str| GamePattern {
+        game: game.to_owned(),
+        rule: WindowRule {
+            title: Some(title.to_owned()),
+            class: Some("UnityWndClass".to_owned()),
+            process: None,
+        },
+    };
+    vec![
+        process("genshin-cn", "YuanShen.exe"),
+        process("genshin-global", "GenshinImpact.exe"),
+        process("star-rail", "StarRail.exe"),
+        title("genshin-cn", "原神"),
+        title("genshin-global", "Genshin Impact"),
+        title("star-rail", "崩坏：星穹铁道"),
+        title("star-rail", "Honkai: Star Rail"),
+    ]
+}
+
+// 配置中的规则优先于内置规则
+pub fn patterns(config: &Config) -> Vec<GamePattern> {
+    config.game_windows.iter().cloned().chain(known_games()).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct GameWindow {
+    pub game: String,
+    pub hwnd: usize,
+    pub pid: u32,
+    pub process: String,
+    pub title: String,
+    pub monitor: String,
+    pub minimized: bool,
+    // 客户区，即游戏画面的位置与分辨率。最小化时保留最小化之前的值
+    pub client: Rect,
+    pub dpi: u32,
+    pub scale: f64,
+    pub logical_client: Size,
+}
+
+pub fn detect(patterns: &[GamePattern], windows: Vec<WindowInfo>) -> Vec<GameWindow> {
+    windows
+        .into_iter()
+        .filter_map(|window| {
+            let pattern = patterns.iter().find(|pattern| pattern.rule.matches(&window))?;
+            Some(GameWindow {
+                game: pattern.game.clone(),
+                hwnd: window.hwnd,
+                pid: window.pid,
+                process: window.process,
+                title: window.title,
+                monitor: window.monitor,
+                minimized: window.minimized,
+                client: window.client,
+                dpi: window.dpi,
+                scale: window.scale,
+                logical_client: window.logical_client,
+            })
+        })
+        .collect()
+}
+
+// 游戏窗口的变化
+#[derive(Debug, Clone)]
+pub enum GameEvent {
+    // 检测到游戏窗口，包括订阅时已经打开的窗口
+    Appeared(GameWindow),
+    Closed(GameWindow),
+    Resized(WindowResized),
+}
+
+impl GameEvent {
+    pub fn message(self) -> ServerMessage {
+        match self {
+            GameEvent::Appeared(window) => ServerMessage::WindowAppeared { data: Box::new(window) },
+            GameEvent::Closed(window) => ServerMessage::WindowClosed { data: Box::new(window) },
+            GameEvent::Resized(resized) => ServerMessage::WindowResized {
+                data: Box::new(resized),
+            },
+        }
+    }
+}
+
+struct WatcherState {
+    // 按出现的先后排列
+    windows: Vec<GameWindow>,
+    live: broadcast::Sender<GameEvent>,
+}
+
+pub struct GameWatcher {
+    patterns: Vec<GamePattern>,
+    windows: Arc<dyn WindowManager>,
+    state: Mutex<WatcherState>,
+}
+
+impl GameWatcher {
+    pub fn new(config: &Config, windows: Arc<dyn WindowManager>) -> GameWatcher {
+        GameWatcher {
+            patterns: patterns(config),
+            windows,
+            state: Mutex::new(WatcherState {
+                windows: Vec::new(),
+                live: broadcast::channel(32).0,
+            }),
+        }
+    }
+
+    // 最近出现的游戏窗口
+    pub fn current(&self) -> Option<GameWindow> {
+        self.state.lock().unwrap().windows.last().cloned()
+    }
+
+    // 当前的游戏窗口与后续事件，两者之间不会遗漏
+    pub fn subscribe(&self) -> (Vec<GameWindow>, broadcast::Receiver<GameEvent>) {
+        let state = self.state.lock().unwrap();
+        (state.windows.clone(), state.live.subscribe())
+    }
+
+    // 定时枚举窗口，直到服务端退出
+    pub async fn run(self: Arc<Self>, interval: Duration, events: Arc<EventBus>) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let watcher = self.clone();
+            let changes = tokio::task::spawn_blocking(move || watcher.poll()).await.unwrap();
+            for event in changes {
+                events.publish(Scope::Windows, &event.message());
+            }
+        }
+    }
+
+    // 枚举一次窗口并更新。枚举失败时跳过这一轮并保留上一次的结果，以免误报窗口关闭
+    fn poll(&self) -> Vec<GameEvent> {
+        match self.windows.list() {
+            Ok(windows) => self.update(detect(&self.patterns, windows)),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    // 与上一次的结果比较，广播并返回发生的变化
+    fn update(&self, detected: Vec<GameWindow>) -> Vec<GameEvent> {
+        let mut state = self.state.lock().unwrap();
+        let mut changes = Vec::new();
+        let mut windows = Vec::with_capacity(detected.len());
+        for old in &state.windows {
+            if !detected.iter().any(|window| window.hwnd == old.hwnd) {
+                changes.push(GameEvent::Closed(old.clone()));
+            }
+        }
+        for old in &state.windows {
+            let Some(mut window) = detected.iter().find(|window| window.hwnd == old.hwnd).cloned() else {
+                continue;
+            };
+            if window.minimized {
+                window.client = old.client;
+                window.logical_client = old.logical_client;
+            } else if (window.client.width, window.client.height) != (old.client.width, old.client.height) {
+                changes.push(GameEvent::Resized(WindowResized {
+                    window: window.clone(),
+                    previous: old.client,
+                }));
+            }
+            windows.push(window);
+        }
+        for window in detected {
+            if !state.windows.iter().any(|old| old.hwnd == window.hwnd) {
+                changes.push(GameEvent::Appeared(window.clone()));
+                windows.push(window);
+            }
+        }
+        state.windows = windows;
+        for event in &changes {
+            // 没有订阅者时发送失败，忽略即可
+            let _ = state.live.send(event.clone());
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::DisplayInfo;
+    use std::io::{Error, ErrorKind, Result};
+
+    // 枚举窗口总是失败，其他操作不会被调用
+    struct Unavailable;
+
+    impl WindowManager for Unavailable {
+        fn list(&self) -> Result<Vec<WindowInfo>> {
+            Err(Error::new(ErrorKind::PermissionDenied, "access denied"))
+        }
+        fn displays(&self) -> Result<Vec<DisplayInfo>> {
+            unreachable!()
+        }
+        fn focus(&self, _hwnd: usize) -> Result<()> {
+            unreachable!()
+        }
+        fn restore(&self, _hwnd: usize) -> Result<()> {
+            unreachable!()
+        }
+        fn minimize(&self, _hwnd: usize) -> Result<()> {
+            unreachable!()
+        }
+        fn bring_to_top(&self, _hwnd: usize) -> Result<()> {
+            unreachable!()
+        }
+        fn move_to(&self, _hwnd: usize, _x: i32, _y: i32) -> Result<()> {
+            unreachable!()
+        }
+        fn resize_client(&self, _hwnd: usize, _width: i32, _height: i32) -> Result<()> {
+            unreachable!()
+        }
+    }
+
+    fn watcher() -> GameWatcher {
+        GameWatcher::new(&Config::default(), Arc::new(Unavailable))
+    }
+
+    fn game(hwnd: usize, width: i32, height: i32) -> GameWindow {
+        GameWindow {
+            game: "genshin-cn".to_owned(),
+            hwnd,
+            pid: 1000,
+            process: "YuanShen.exe".to_owned(),
+            title: "原神".to_owned(),
+            monitor: "stub".to_owned(),
+            minimized: false,
+            client: Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+            dpi: 96,
+            scale: 1.0,
+            logical_client: Size { width, height },
+        }
+    }
+
+    #[test]
+    fn reports_appeared_windows() {
+        let watcher = watcher();
+        let (current, mut live) = watcher.subscribe();
+        assert!(current.is_empty());
+        let changes = watcher.update(vec![game(1, 1920, 1080)]);
+        assert!(matches!(changes[..], [GameEvent::Appeared(ref window)] if window.hwnd == 1));
+        assert!(matches!(live.try_recv(), Ok(GameEvent::Appeared(window)) if window.hwnd == 1));
+        assert_eq!(watcher.current(), Some(game(1, 1920, 1080)));
+    }
+
+    #[test]
+    fn reports_closed_windows() {
+        let watcher = watcher();
+        watcher.update(vec![game(1, 1920, 1080), game(2, 1600, 900)]);
+        let changes = watcher.update(vec![game(2, 1600, 900)]);
+        assert!(matches!(changes[..], [GameEvent::Closed(ref window)] if window.hwnd == 1));
+        assert_eq!(watcher.subscribe().0, vec![game(2, 1600, 900)]);
+    }
+
+    #[test]
+    fn reports_resized_windows() {
+        let watcher = watcher();
+        watcher.update(vec![game(1, 1920, 1080)]);
+        let changes = watcher.update(vec![game(1, 2560, 1440)]);
+        let [GameEvent::Resized(resized)] = &changes[..] else {
+            panic!("expected a resize, got {changes:?}");
+        };
+        assert_eq!(resized.window, game(1, 2560, 1440));
+        assert_eq!((resized.previous.width, resized.previous.height), (1920, 1080));
+        // 最小化不算改变分辨率，保留最小化之前的客户区
+        let minimized = GameWindow {
+            minimized: true,
+            ..game(1, 0, 0)
+        };
+        assert!(watcher.update(vec![minimized]).is_empty());
+        assert_eq!(watcher.current().unwrap().client.width, 2560);
+    }
+
+    #[test]
+    fn unchanged_windows_report_nothing() {
+        let watcher = watcher();
+        watcher.update(vec![game(1, 1920, 1080), game(2, 1600, 900)]);
+        let (_, mut live) = watcher.subscribe();
+        // 只移动位置不算改变
+        let mut moved = game(1, 1920, 1080);
+        moved.client.x = 100;
+        assert!(watcher.update(vec![game(2, 1600, 900), moved]).is_empty());
+        assert!(live.try_recv().is_err());
+        // 保持出现的先后
+        assert_eq!(watcher.current().unwrap().hwnd, 2);
+    }
+
+    #[test]
+    fn failed_enumeration_keeps_the_windows() {
+        let watcher = watcher();
+        watcher.update(vec![game(1, 1920, 1080)]);
+        assert!(watcher.poll().is_empty());
+        assert_eq!(watcher.current(), Some(game(1, 1920, 1080)));
+    }
+}
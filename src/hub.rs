@@ -0,0 +1,284 @@
+// 扫描输出的广播中心：缓冲最近一次扫描的输出，并转发给所有订阅该扫描的连接。
+// 订阅者分为只读的观看者与至多一个控制者，进出都不影响 yas 的运行。
+
+use crate::scan::OutputLine;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{broadcast, Notify};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    // 只接收输出
+    #[default]
+    Viewer,
+    // 还可以取消扫描，同一时间只有一个
+    Controller,
+}
+
+#[derive(Default)]
+struct HubState {
+    id: Option<Uuid>,
+    lines: VecDeque<OutputLine>,
+    next_seq: u64,
+    // 扫描结束后置空，订阅者随之收到关闭
+    live: Option<broadcast::Sender<OutputLine>>,
+    // 控制者所在连接的 id
+    controller: Option<Uuid>,
+    subscribers: usize,
+    cancel: Option<Arc<Notify>>,
+}
+
+pub struct ScanHub {
+    state: Mutex<HubState>,
+    buffer_lines: usize,
+}
+
+// 订阅时拿到的内容：错过的输出，以及扫描仍在运行时的后续输出。
+// 丢弃后即退出订阅，控制者退出后其他连接可以重新申请控制。
+pub struct Subscription {
+    pub lines: Vec<OutputLine>,
+    pub live: Option<broadcast::Receiver<OutputLine>>,
+    pub role: Role,
+    hub: Arc<ScanHub>,
+    scan: Uuid,
+    connection: Uuid,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut state = self.hub.state.lock().unwrap();
+        if state.id != Some(self.scan) {
+            return;
+        }
+        state.subscribers -= 1;
+        if self.role == Role::Controller && state.controller == Some(self.connection) {
+            state.controller = None;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum HubError {
+    // 不是正在运行或最近一次的扫描
+    NotFound,
+    // 已有其他连接在控制该扫描
+    ControllerTaken,
+    // 该连接不是扫描的控制者
+    NotController,
+}
+
+// 正在运行的扫描的订阅情况
+#[derive(Debug, Clone, Serialize)]
+pub struct Presence {
+    pub subscribers: usize,
+    pub controlled: bool,
+}
+
+impl ScanHub {
+    pub fn new(buffer_lines: usize) -> ScanHub {
+        ScanHub {
+            state: Mutex::new(HubState::default()),
+            buffer_lines: buffer_lines.max(1),
+        }
+    }
+
+    // 开始新的扫描，清空上一次的输出，返回取消信号
+    pub fn open(&self, id: Uuid) -> Arc<Notify> {
+        let cancel = Arc::new(Notify::new());
+        *self.state.lock().unwrap() = HubState {
+            id: Some(id),
+            lines: VecDeque::with_capacity(self.buffer_lines),
+            next_seq: 0,
+            live: Some(broadcast::channel(self.buffer_lines).0),
+            controller: None,
+            subscribers: 0,
+            cancel: Some(cancel.clone()),
+        };
+        cancel
+    }
+
+    // 扫描结束，输出仍保留供之后的订阅者补齐
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.live = None;
+        state.controller = None;
+        state.cancel = None;
+    }
+
+    // 补发 `since` 及之后仍在缓冲区中的输出，并订阅后续输出。
+    // 扫描已结束时只能以观看者的身份订阅。
+    pub fn subscribe(
+        self: &Arc<Self>,
+        scan: &Uuid,
+        since: u64,
+        connection: Uuid,
+        role: Role,
+    ) -> Result<Subscription, HubError> {
+        let mut state = self.state.lock().unwrap();
+        if state.id.as_ref() != Some(scan) {
+            return Err(HubError::NotFound);
+        }
+        let role = match role {
+            Role::Controller if state.live.is_none() => Role::Viewer,
+            Role::Controller if state.controller.is_some_and(|c| c != connection) => {
+                return Err(HubError::ControllerTaken);
+            },
+            Role::Controller => {
+                state.controller = Some(connection);
+                Role::Controller
+            },
+            Role::Viewer => Role::Viewer,
+        };
+        state.subscribers += 1;
+        Ok(Subscription {
+            lines: state.lines.iter().filter(|line| line.seq >= since).cloned().collect(),
+            live: state.live.as_ref().map(broadcast::Sender::subscribe),
+            role,
+            hub: self.clone(),
+            scan: *scan,
+            connection,
+        })
+    }
+
+    pub fn publish(&self, line: impl FnOnce(u64) -> OutputLine) -> OutputLine {
+        let mut state = self.state.lock().unwrap();
+        let line = line(state.next_seq);
+        state.next_seq += 1;
+        if state.lines.len() == self.buffer_lines {
+            state.lines.pop_front();
+        }
+        state.lines.push_back(line.clone());
+        if let Some(live) = &state.live {
+            // 没有订阅者时发送失败，忽略即可
+            let _ = live.send(line.clone());
+        }
+        line
+    }
+
+    pub fn presence(&self, scan: &Uuid) -> Option<Presence> {
+        let state = self.state.lock().unwrap();
+        (state.id.as_ref() == Some(scan) && state.live.is_some()).then(|| Presence {
+            subscribers: state.subscribers,
+            controlled: state.controller.is_some(),
+        })
+    }
+
+    // 只有正在运行的扫描的控制者可以取消
+    pub fn cancel(&self, scan: &Uuid, connection: Uuid) -> Result<(), HubError> {
+        let state = self.state.lock().unwrap();
+        let Some(cancel) = state.cancel.as_ref().filter(|_| state.id.as_ref() == Some(scan)) else {
+            return Err(HubError::NotFound);
+        };
+        if state.controller != Some(connection) {
+            return Err(HubError::NotController);
+        }
+        cancel.notify_one();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::Stream;
+
+    fn hub(buffer_lines: usize) -> (Arc<ScanHub>, Uuid) {
+        let hub = Arc::new(ScanHub::new(buffer_lines));
+        let scan = Uuid::new_v4();
+        hub.open(scan);
+        (hub, scan)
+    }
+
+    fn publish(hub: &ScanHub, text: &str) {
+        hub.publish(|seq| OutputLine {
+            seq,
+            time: String::new(),
+            stream: Stream::Stdout,
+            text: text.to_owned(),
+        });
+    }
+
+    fn texts(lines: &[OutputLine]) -> Vec<&str> {
+        lines.iter().map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn only_one_controller_at_a_time() {
+        let (hub, scan) = hub(8);
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let controller = hub.subscribe(&scan, 0, first, Role::Controller).unwrap();
+        assert_eq!(controller.role, Role::Controller);
+        assert!(matches!(
+            hub.subscribe(&scan, 0, second, Role::Controller),
+            Err(HubError::ControllerTaken)
+        ));
+        // 同一连接可以再次以控制者订阅
+        let again = hub.subscribe(&scan, 0, first, Role::Controller).unwrap();
+        assert_eq!(again.role, Role::Controller);
+        let presence = hub.presence(&scan).unwrap();
+        assert_eq!((presence.subscribers, presence.controlled), (2, true));
+    }
+
+    #[test]
+    fn viewers_cannot_cancel() {
+        let (hub, scan) = hub(8);
+        let (controller, viewer) = (Uuid::new_v4(), Uuid::new_v4());
+        let _controller = hub.subscribe(&scan, 0, controller, Role::Controller).unwrap();
+        let _viewer = hub.subscribe(&scan, 0, viewer, Role::Viewer).unwrap();
+        assert!(matches!(hub.cancel(&scan, viewer), Err(HubError::NotController)));
+        assert!(matches!(
+            hub.cancel(&Uuid::new_v4(), controller),
+            Err(HubError::NotFound)
+        ));
+        assert!(hub.cancel(&scan, controller).is_ok());
+        // 扫描结束后无法再取消
+        hub.close();
+        assert!(matches!(hub.cancel(&scan, controller), Err(HubError::NotFound)));
+    }
+
+    #[test]
+    fn dropping_the_controller_frees_the_slot() {
+        let (hub, scan) = hub(8);
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let controller = hub.subscribe(&scan, 0, first, Role::Controller).unwrap();
+        let viewer = hub.subscribe(&scan, 0, second, Role::Viewer).unwrap();
+        drop(controller);
+        let presence = hub.presence(&scan).unwrap();
+        assert_eq!((presence.subscribers, presence.controlled), (1, false));
+        let controller = hub.subscribe(&scan, 0, second, Role::Controller).unwrap();
+        assert_eq!(controller.role, Role::Controller);
+        assert!(matches!(hub.cancel(&scan, first), Err(HubError::NotController)));
+        drop(viewer);
+        assert!(hub.cancel(&scan, second).is_ok());
+    }
+
+    #[test]
+    fn late_subscribers_see_the_backlog() {
+        let (hub, scan) = hub(3);
+        for text in ["a", "b", "c", "d"] {
+            publish(&hub, text);
+        }
+        // 缓冲区只保留最近的 3 行
+        let mut late = hub.subscribe(&scan, 0, Uuid::new_v4(), Role::Viewer).unwrap();
+        assert_eq!(texts(&late.lines), ["b", "c", "d"]);
+        let resumed = hub.subscribe(&scan, 3, Uuid::new_v4(), Role::Viewer).unwrap();
+        assert_eq!(texts(&resumed.lines), ["d"]);
+
+        let mut live = late.live.take().unwrap();
+        publish(&hub, "e");
+        assert_eq!(live.try_recv().unwrap().text, "e");
+        // 扫描结束后仍可补齐输出，但只能观看
+        hub.close();
+        let finished = hub.subscribe(&scan, 0, Uuid::new_v4(), Role::Controller).unwrap();
+        assert_eq!(finished.role, Role::Viewer);
+        assert!(finished.live.is_none());
+        assert_eq!(texts(&finished.lines), ["c", "d", "e"]);
+    }
+}
@@ -0,0 +1,527 @@
+// 模拟输入：网页端的扫描器通过 `POST /api/windows/{hwnd}/input` 在游戏窗口内点击、滚动与按键。
+// 坐标相对于客户区，超出客户区的操作会被拒绝。同一时间只执行一个操作序列，事件之间至少间隔
+// `1 / input_max_events_per_second` 秒。用户按下紧急停止热键后拒绝所有输入，直到再次按下。
+
+use crate::{
+    config::Config,
+    platform::{notify_message, InputEvent, InputInjector, MouseButton, Rect},
+    window_control::{ActionResult, ActionStatus},
+};
+
+use serde::Deserialize;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, TryLockError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+// 单个请求最多包含的操作数
+pub const MAX_ACTIONS: usize = 200;
+// 单个 `wait` 最长的时间（毫秒）
+pub const MAX_WAIT_MS: u64 = 5000;
+// 单个 `click` 最多的点击次数
+pub const MAX_CLICKS: u32 = 10;
+
+const DEFAULT_HOTKEY: &str = "Ctrl+Alt+Q";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum InputAction {
+    Move {
+        x: i32,
+        y: i32,
+    },
+    // 省略坐标时在鼠标当前的位置操作
+    Click {
+        x: Option<i32>,
+        y: Option<i32>,
+        #[serde(default)]
+        button: MouseButton,
+        #[serde(default = "one")]
+        count: u32,
+    },
+    MouseDown {
+        x: Option<i32>,
+        y: Option<i32>,
+        #[serde(default)]
+        button: MouseButton,
+    },
+    MouseUp {
+        x: Option<i32>,
+        y: Option<i32>,
+        #[serde(default)]
+        button: MouseButton,
+    },
+    // 滚轮的格数，正数向上
+    Scroll {
+        x: Option<i32>,
+        y: Option<i32>,
+        notches: i32,
+    },
+    // 按下并抬起
+    Key {
+        key: String,
+    },
+    KeyDown {
+        key: String,
+    },
+    KeyUp {
+        key: String,
+    },
+    Wait {
+        ms: u64,
+    },
+}
+
+fn one() -> u32 {
+    1
+}
+
+impl InputAction {
+    fn name(&self) -> &'static str {
+        match self {
+            InputAction::Move { .. } => "move",
+            InputAction::Click { .. } => "click",
+            InputAction::MouseDown { .. } => "mouse-down",
+            InputAction::MouseUp { .. } => "mouse-up",
+            InputAction::Scroll { .. } => "scroll",
+            InputAction::Key { .. } => "key",
+            InputAction::KeyDown { .. } => "key-down",
+            InputAction::KeyUp { .. } => "key-up",
+            InputAction::Wait { .. } => "wait",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InputRequest {
+    pub actions: Vec<InputAction>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Send(InputEvent),
+    Wait(Duration),
+}
+
+// 一个操作展开后的事件
+#[derive(Debug, Clone)]
+pub struct PlannedAction {
+    name: &'static str,
+    steps: Vec<Step>,
+}
+
+// 检查所有操作并换算为屏幕坐标的事件，任何一个不合法时都不会执行
+pub fn plan(actions: &[InputAction], client: Rect) -> Result<Vec<PlannedAction>, String> {
+    if actions.len() > MAX_ACTIONS {
+        return Err(format!("at most {MAX_ACTIONS} actions per request"));
+    }
+    actions
+        .iter()
+        .enumerate()
+        .map(|(index, action)| {
+            let steps = plan_action(action, client).map_err(|err| format!("action {index}: {err}"))?;
+            Ok(PlannedAction {
+                name: action.name(),
+                steps,
+            })
+        })
+        .collect()
+}
+
+fn plan_action(action: &InputAction, client: Rect) -> Result<Vec<Step>, String> {
+    let point = |x: i32, y: i32| {
+        if (0..client.width).contains(&x) && (0..client.height).contains(&y) {
+            Ok(Step::Send(InputEvent::MouseMove {
+                x: client.x + x,
+                y: client.y + y,
+            }))
+        } else {
+            Err(format!(
+                "({x}, {y}) is outside the {}×{} client area",
+                client.width, client.height
+            ))
+        }
+    };
+    // 坐标要么都给出，要么都省略
+    let optional_point = |x: Option<i32>, y: Option<i32>| match (x, y) {
+        (Some(x), Some(y)) => point(x, y).map(Some),
+        (None, None) => Ok(None),
+        _ => Err("x and y must be given together".to_owned()),
+    };
+    let key = |name: &str| key_code(name).ok_or_else(|| format!("unknown key `{name}`"));
+    let button = |button, down| Step::Send(InputEvent::MouseButton { button, down });
+    let mut steps = Vec::new();
+    match action {
+        InputAction::Move { x, y } => steps.push(point(*x, *y)?),
+        InputAction::Click {
+            x,
+            y,
+            button: which,
+            count,
+        } => {
+            if !(1..=MAX_CLICKS).contains(count) {
+                return Err(format!("count must be between 1 and {MAX_CLICKS}"));
+            }
+            steps.extend(optional_point(*x, *y)?);
+            for _ in 0..*count {
+                steps.push(button(*which, true));
+                steps.push(button(*which, false));
+            }
+        },
+        InputAction::MouseDown { x, y, button: which } | InputAction::MouseUp { x, y, button: which } => {
+            steps.extend(optional_point(*x, *y)?);
+            steps.push(button(*which, matches!(action, InputAction::MouseDown { .. })));
+        },
+        InputAction::Scroll { x, y, notches } => {
+            steps.extend(optional_point(*x, *y)?);
+            steps.push(Step::Send(InputEvent::Scroll { notches: *notches }));
+        },
+        InputAction::Key { key: name } => {
+            let code = key(name)?;
+            steps.push(Step::Send(InputEvent::Key { code, down: true }));
+            steps.push(Step::Send(InputEvent::Key { code, down: false }));
+        },
+        InputAction::KeyDown { key: name } | InputAction::KeyUp { key: name } => {
+            let down = matches!(action, InputAction::KeyDown { .. });
+            steps.push(Step::Send(InputEvent::Key { code: key(name)?, down }));
+        },
+        InputAction::Wait { ms } if *ms > MAX_WAIT_MS => return Err(format!("wait at most {MAX_WAIT_MS} ms")),
+        InputAction::Wait { ms } => steps.push(Step::Wait(Duration::from_millis(*ms))),
+    }
+    Ok(steps)
+}
+
+// 按键名对应的虚拟键码，不区分大小写：单个字母或数字、`F1` 到 `F24`，以及常用的功能键
+pub fn key_code(name: &str) -> Option<u16> {
+    let upper = name.to_ascii_uppercase();
+    if let [c] = upper.as_bytes() {
+        return c.is_ascii_alphanumeric().then_some(*c as u16);
+    }
+    if let Some(n) = upper.strip_prefix('F').and_then(|n| n.parse::<u16>().ok()) {
+        return (1..=24).contains(&n).then_some(0x70 + n - 1);
+    }
+    Some(match upper.as_str() {
+        "BACKSPACE" => 0x08,
+        "TAB" => 0x09,
+        "ENTER" => 0x0d,
+        "SHIFT" => 0x10,
+        "CTRL" | "CONTROL" => 0x11,
+        "ALT" => 0x12,
+        "PAUSE" => 0x13,
+        "ESC" | "ESCAPE" => 0x1b,
+        "SPACE" => 0x20,
+        "PAGEUP" => 0x21,
+        "PAGEDOWN" => 0x22,
+        "END" => 0x23,
+        "HOME" => 0x24,
+        "LEFT" => 0x25,
+        "UP" => 0x26,
+        "RIGHT" => 0x27,
+        "DOWN" => 0x28,
+        "INSERT" => 0x2d,
+        "DELETE" => 0x2e,
+        _ => return None,
+    })
+}
+
+// `Ctrl+Alt+Q` 形式的热键
+pub fn parse_hotkey(hotkey: &str) -> Option<Vec<u16>> {
+    hotkey.split('+').map(|name| key_code(name.trim())).collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum InputError {
+    // 已有操作序列在执行，对应 429
+    Busy,
+    // 紧急停止中，对应 423
+    Stopped,
+}
+
+pub struct InputController {
+    injector: Arc<dyn InputInjector>,
+    hotkey: String,
+    hotkey_keys: Vec<u16>,
+    interval: Duration,
+    stopped: AtomicBool,
+    // 上一个事件的发送时间，同时保证同一时间只执行一个序列
+    running: Mutex<Option<Instant>>,
+}
+
+impl InputController {
+    pub fn new(config: &Config, injector: Arc<dyn InputInjector>) -> InputController {
+        let (hotkey, hotkey_keys) = match parse_hotkey(&config.input_stop_hotkey) {
+            Some(keys) => (config.input_stop_hotkey.clone(), keys),
+            None => {
+                eprintln!(
+                    "无法识别紧急停止热键 {}，使用 {DEFAULT_HOTKEY}",
+                    config.input_stop_hotkey
+                );
+                (DEFAULT_HOTKEY.to_owned(), parse_hotkey(DEFAULT_HOTKEY).unwrap())
+            },
+        };
+        InputController {
+            injector,
+            hotkey,
+            hotkey_keys,
+            interval: Duration::from_secs_f64(1.0 / config.input_max_events_per_second.max(1) as f64),
+            stopped: AtomicBool::new(false),
+            running: Mutex::new(None),
+        }
+    }
+
+    pub fn hotkey(&self) -> &str {
+        &self.hotkey
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    pub fn max_events_per_second(&self) -> f64 {
+        1.0 / self.interval.as_secs_f64()
+    }
+
+    // 依次执行，在阻塞线程中调用。某个操作失败或被紧急停止后，其余操作不再执行，
+    // 按下而没有抬起的键与鼠标按钮会在结束时抬起
+    pub fn run(&self, actions: Vec<PlannedAction>) -> Result<Vec<ActionResult>, InputError> {
+        let mut last_sent = match self.running.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => return Err(InputError::Busy),
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+        };
+        if self.stopped() {
+            return Err(InputError::Stopped);
+        }
+        let mut held = Vec::new();
+        let mut failed = false;
+        let results = actions
+            .into_iter()
+            .map(|action| {
+                let status = if failed {
+                    ActionStatus::Skipped
+                } else {
+                    match self.run_steps(&action.steps, &mut last_sent, &mut held) {
+                        Ok(()) => ActionStatus::Done,
+                        Err(error) => {
+                            failed = true;
+                            ActionStatus::Failed { error }
+                        },
+                    }
+                };
+                ActionResult {
+                    action: action.name,
+                    status,
+                }
+            })
+            .collect();
+        for event in held.into_iter().rev() {
+            let _ = self.injector.send(event);
+        }
+        Ok(results)
+    }
+
+    fn run_steps(
+        &self,
+        steps: &[Step],
+        last_sent: &mut Option<Instant>,
+        held: &mut Vec<InputEvent>,
+    ) -> Result<(), String> {
+        for step in steps {
+            match *step {
+                Step::Wait(duration) => self.sleep(duration)?,
+                Step::Send(event) => {
+                    if let Some(last) = *last_sent {
+                        self.sleep(self.interval.saturating_sub(last.elapsed()))?;
+                    }
+                    if self.stopped() {
+                        return Err("stopped".to_owned());
+                    }
+                    self.injector.send(event).map_err(|err| err.to_string())?;
+                    *last_sent = Some(Instant::now());
+                    track_held(held, event);
+                },
+            }
+        }
+        Ok(())
+    }
+
+    // 分段等待，以便及时响应紧急停止
+    fn sleep(&self, duration: Duration) -> Result<(), String> {
+        let deadline = Instant::now() + duration;
+        loop {
+            if self.stopped() {
+                return Err("stopped".to_owned());
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(());
+            }
+            thread::sleep(remaining.min(Duration::from_millis(20)));
+        }
+    }
+
+    // 检查一次热键，按下的瞬间切换停止状态，返回是否切换
+    pub fn poll_hotkey(&self, was_pressed: &mut bool) -> bool {
+        let pressed = self.injector.pressed(&self.hotkey_keys);
+        let toggled = pressed && !*was_pressed;
+        *was_pressed = pressed;
+        if toggled {
+            self.stopped.fetch_xor(true, Ordering::SeqCst);
+        }
+        toggled
+    }
+
+    // 定时检查紧急停止热键，直到服务端退出
+    pub async fn watch_hotkey(self: Arc<Self>) {
+        let mut ticker = tokio::time::interval(Duration::from_millis(50));
+        let mut was_pressed = false;
+        loop {
+            ticker.tick().await;
+            if self.poll_hotkey(&mut was_pressed) {
+                let message = match self.stopped() {
+                    true => format!("已停止模拟输入，再次按下 {} 恢复", self.hotkey),
+                    false => "已恢复模拟输入".to_owned(),
+                };
+                let _ = notify_message("frostflake", &message);
+            }
+        }
+    }
+}
+
+fn track_held(held: &mut Vec<InputEvent>, event: InputEvent) {
+    let release = match event {
+        InputEvent::MouseButton { button, .. } => InputEvent::MouseButton { button, down: false },
+        InputEvent::Key { code, .. } => InputEvent::Key { code, down: false },
+        _ => return,
+    };
+    held.retain(|held| *held != release);
+    if event != release {
+        held.push(release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::RecordingInjector;
+
+    fn client() -> Rect {
+        Rect {
+            x: 100,
+            y: 50,
+            width: 1280,
+            height: 720,
+        }
+    }
+
+    fn controller(injector: Arc<RecordingInjector>) -> InputController {
+        let config = Config {
+            input_max_events_per_second: 1000,
+            ..Config::default()
+        };
+        InputController::new(&config, injector)
+    }
+
+    fn parse(json: &str) -> Vec<InputAction> {
+        serde_json::from_str::<InputRequest>(json).unwrap().actions
+    }
+
+    #[test]
+    fn sequence_is_relative_to_client() {
+        let injector = Arc::new(RecordingInjector::default());
+        let actions = parse(
+            r#"{"actions": [
+                {"type": "click", "x": 10, "y": 20},
+                {"type": "scroll", "notches": -3},
+                {"type": "key", "key": "esc"}
+            ]}"#,
+        );
+        let results = controller(injector.clone())
+            .run(plan(&actions, client()).unwrap())
+            .unwrap();
+        assert!(results.iter().all(|result| result.status == ActionStatus::Done));
+        let left = MouseButton::Left;
+        assert_eq!(
+            injector.events(),
+            vec![
+                InputEvent::MouseMove { x: 110, y: 70 },
+                InputEvent::MouseButton {
+                    button: left,
+                    down: true
+                },
+                InputEvent::MouseButton {
+                    button: left,
+                    down: false
+                },
+                InputEvent::Scroll { notches: -3 },
+                InputEvent::Key { code: 0x1b, down: true },
+                InputEvent::Key {
+                    code: 0x1b,
+                    down: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_actions_are_rejected_before_sending() {
+        for json in [
+            r#"{"actions": [{"type": "move", "x": 0, "y": 0}, {"type": "move", "x": 1280, "y": 0}]}"#,
+            r#"{"actions": [{"type": "click", "x": 10}]}"#,
+            r#"{"actions": [{"type": "key", "key": "Hyper"}]}"#,
+            r#"{"actions": [{"type": "wait", "ms": 60000}]}"#,
+        ] {
+            assert!(plan(&parse(json), client()).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn emergency_stop_releases_held_keys() {
+        let injector = Arc::new(RecordingInjector::default());
+        let controller = Arc::new(controller(injector.clone()));
+        let actions = parse(
+            r#"{"actions": [
+                {"type": "key-down", "key": "shift"},
+                {"type": "wait", "ms": 2000},
+                {"type": "key", "key": "a"}
+            ]}"#,
+        );
+        let planned = plan(&actions, client()).unwrap();
+        let running = thread::spawn({
+            let controller = controller.clone();
+            move || controller.run(planned)
+        });
+        thread::sleep(Duration::from_millis(100));
+        let mut was_pressed = false;
+        injector.hold(&parse_hotkey("ctrl+alt+q").unwrap());
+        assert!(controller.poll_hotkey(&mut was_pressed));
+        // 按住不放不会再次切换
+        assert!(!controller.poll_hotkey(&mut was_pressed));
+
+        let results = running.join().unwrap().unwrap();
+        assert_eq!(results[0].status, ActionStatus::Done);
+        assert!(matches!(results[1].status, ActionStatus::Failed { .. }));
+        assert_eq!(results[2].status, ActionStatus::Skipped);
+        assert_eq!(
+            injector.events(),
+            vec![
+                InputEvent::Key { code: 0x10, down: true },
+                InputEvent::Key {
+                    code: 0x10,
+                    down: false
+                },
+            ]
+        );
+        assert_eq!(controller.run(Vec::new()).unwrap_err(), InputError::Stopped);
+
+        injector.hold(&[]);
+        controller.poll_hotkey(&mut was_pressed);
+        injector.hold(&parse_hotkey("ctrl+alt+q").unwrap());
+        controller.poll_hotkey(&mut was_pressed);
+        assert!(!controller.stopped());
+    }
+}
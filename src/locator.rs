@@ -0,0 +1,417 @@
+// 查找已安装的游戏并启动。网页端发起扫描时游戏往往还没有打开，`GET /api/games` 列出本机安装的客户端，
+// `POST /api/games/{id}/launch` 启动游戏，并可以等待游戏窗口出现后再返回。
+//
+// 安装位置来自三处：米哈游启动器（HoYoPlay）在注册表中记录的游戏目录；旧版启动器在“卸载程序”中
+// 记录的启动器目录，其下 `config.ini` 的 `game_install_path` 为游戏目录；Epic 启动器的
+// `LauncherInstalled.dat`。
+
+use crate::{
+    game::{GameEvent, GameWatcher, GameWindow},
+    platform::{Hive, RegistryStore},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+// 等待游戏窗口的默认与最长时间
+pub const DEFAULT_WAIT_MS: u64 = 60_000;
+pub const MAX_WAIT_MS: u64 = 300_000;
+
+const UNINSTALL_KEYS: [&str; 2] = [
+    r"SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall",
+    r"SOFTWARE\WOW6432Node\Microsoft\Windows\CurrentVersion\Uninstall",
+];
+
+// 一个游戏客户端
+struct Client {
+    id: &'static str,
+    // 游戏窗口的标识，与 `game::known_games` 相同
+    game: &'static str,
+    name: &'static str,
+    exe: &'static str,
+    // HoYoPlay 在 HKCU 下记录该游戏的键
+    hoyoplay: Option<&'static str>,
+    // 旧版启动器在“卸载程序”中的名称
+    launcher: Option<&'static str>,
+    epic: bool,
+}
+
+const CLIENTS: [Client; 6] = [
+    Client {
+        id: "genshin-cn",
+        game: "genshin-cn",
+        name: "原神",
+        exe: "YuanShen.exe",
+        hoyoplay: Some(r"Software\miHoYo\HYP\1_1\hk4e_cn"),
+        launcher: Some("原神"),
+        epic: false,
+    },
+    Client {
+        id: "genshin-global",
+        game: "genshin-global",
+        name: "Genshin Impact",
+        exe: "GenshinImpact.exe",
+        hoyoplay: Some(r"Software\Cognosphere\HYP\1_0\hk4e_global"),
+        launcher: Some("Genshin Impact"),
+        epic: false,
+    },
+    Client {
+        id: "genshin-epic",
+        game: "genshin-global",
+        name: "Genshin Impact (Epic)",
+        exe: "GenshinImpact.exe",
+        hoyoplay: None,
+        launcher: None,
+        epic: true,
+    },
+    Client {
+        id: "star-rail-cn",
+        game: "star-rail",
+        name: "崩坏：星穹铁道",
+        exe: "StarRail.exe",
+        hoyoplay: Some(r"Software\miHoYo\HYP\1_1\hkrpg_cn"),
+        launcher: Some("崩坏：星穹铁道"),
+        epic: false,
+    },
+    Client {
+        id: "star-rail-global",
+        game: "star-rail",
+        name: "Honkai: Star Rail",
+        exe: "StarRail.exe",
+        hoyoplay: Some(r"Software\Cognosphere\HYP\1_0\hkrpg_global"),
+        launcher: Some("Honkai: Star Rail"),
+        epic: false,
+    },
+    Client {
+        id: "star-rail-epic",
+        game: "star-rail",
+        name: "Honkai: Star Rail (Epic)",
+        exe: "StarRail.exe",
+        hoyoplay: None,
+        launcher: None,
+        epic: true,
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Hoyoplay,
+    Launcher,
+    Epic,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InstalledGame {
+    pub id: String,
+    pub game: String,
+    pub name: String,
+    pub source: Source,
+    // 游戏的可执行文件
+    pub exe: PathBuf,
+    // Epic 的游戏需要通过 Epic 启动器启动，否则无法登录
+    #[serde(skip)]
+    pub epic_app: Option<String>,
+}
+
+pub struct GameLocator {
+    registry: Arc<dyn RegistryStore>,
+    epic_manifest: PathBuf,
+}
+
+impl GameLocator {
+    pub fn new(registry: Arc<dyn RegistryStore>) -> GameLocator {
+        let program_data = std::env::var_os("ProgramData").unwrap_or_else(|| r"C:\ProgramData".into());
+        let epic_manifest = Path::new(&program_data).join(r"Epic\UnrealEngineLauncher\LauncherInstalled.dat");
+        GameLocator::with_epic_manifest(registry, epic_manifest)
+    }
+
+    pub fn with_epic_manifest(registry: Arc<dyn RegistryStore>, epic_manifest: PathBuf) -> GameLocator {
+        GameLocator {
+            registry,
+            epic_manifest,
+        }
+    }
+
+    // 本机安装的游戏客户端，可执行文件不存在的记录会被忽略
+    pub fn locate(&self) -> Vec<InstalledGame> {
+        let epic = read_epic_manifest(&self.epic_manifest);
+        CLIENTS
+            .iter()
+            .filter_map(|client| {
+                let found = |source, exe, epic_app| InstalledGame {
+                    id: client.id.to_owned(),
+                    game: client.game.to_owned(),
+                    name: client.name.to_owned(),
+                    source,
+                    exe,
+                    epic_app,
+                };
+                if let Some(exe) = client.hoyoplay.and_then(|key| self.hoyoplay(key, client.exe)) {
+                    return Some(found(Source::Hoyoplay, exe, None));
+                }
+                if let Some(exe) = client.launcher.and_then(|name| self.launcher(name, client.exe)) {
+                    return Some(found(Source::Launcher, exe, None));
+                }
+                if client.epic {
+                    let (exe, app) = epic
+                        .iter()
+                        .find_map(|(dir, app)| Some((find_exe(dir, client.exe)?, app.clone())))?;
+                    return Some(found(Source::Epic, exe, Some(app)));
+                }
+                None
+            })
+            .collect()
+    }
+
+    pub fn find(&self, id: &str) -> Option<InstalledGame> {
+        self.locate().into_iter().find(|game| game.id == id)
+    }
+
+    // 读取失败与不存在一样视为没有安装
+    fn read_string(&self, hive: Hive, path: &str, name: &str) -> Option<String> {
+        self.registry.get_string(hive, path, name).ok().flatten()
+    }
+
+    fn hoyoplay(&self, key: &str, exe: &str) -> Option<PathBuf> {
+        let dir = self.read_string(Hive::CurrentUser, key, "GameInstallPath")?;
+        find_exe(Path::new(&dir), exe)
+    }
+
+    fn launcher(&self, name: &str, exe: &str) -> Option<PathBuf> {
+        [Hive::LocalMachine, Hive::CurrentUser]
+            .into_iter()
+            .flat_map(|hive| UNINSTALL_KEYS.map(|key| (hive, format!(r"{key}\{name}"))))
+            .filter_map(|(hive, key)| self.read_string(hive, &key, "InstallPath"))
+            .find_map(|launcher_dir| {
+                let config = fs::read_to_string(Path::new(&launcher_dir).join("config.ini")).ok()?;
+                find_exe(Path::new(&game_install_path(&config)?), exe)
+            })
+    }
+}
+
+// 旧版启动器 `config.ini` 中 `[launcher]` 一节的 `game_install_path`
+fn game_install_path(config: &str) -> Option<String> {
+    config.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        (key.trim() == "game_install_path" && !value.trim().is_empty()).then(|| value.trim().to_owned())
+    })
+}
+
+// Epic 启动器中已安装的游戏：安装目录与应用名
+fn read_epic_manifest(path: &Path) -> Vec<(PathBuf, String)> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let Ok(manifest) = serde_json::from_str::<Value>(&content) else {
+        return Vec::new();
+    };
+    manifest["InstallationList"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| {
+            let dir = item["InstallLocation"].as_str()?;
+            let app = item["AppName"].as_str()?;
+            Some((PathBuf::from(dir), app.to_owned()))
+        })
+        .collect()
+}
+
+// 可执行文件在游戏目录下，或在其下一层的子目录中，如 `Genshin Impact Game`
+fn find_exe(dir: &Path, exe: &str) -> Option<PathBuf> {
+    let path = dir.join(exe);
+    if path.is_file() {
+        return Some(path);
+    }
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().join(exe))
+        .find(|path| path.is_file())
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LaunchRequest {
+    // 是否等待游戏窗口出现后再返回
+    pub wait: bool,
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Launched {
+    // 游戏已经在运行时为 `false`，不会再次启动
+    pub launched: bool,
+    // 通过 Epic 启动器启动时为空
+    pub pid: Option<u32>,
+    // 只在等待时给出
+    pub window: Option<GameWindow>,
+}
+
+#[derive(Debug)]
+pub enum LaunchError {
+    // 启动失败，对应 500
+    Failed(io::Error),
+    // 等待游戏窗口超时，对应 504
+    Timeout,
+}
+
+impl std::fmt::Display for LaunchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LaunchError::Failed(err) => write!(f, "failed to launch the game: {err}"),
+            LaunchError::Timeout => write!(f, "timed out waiting for the game window"),
+        }
+    }
+}
+
+// 启动游戏，`wait` 不为空时等待游戏窗口出现。同一游戏的窗口已经存在时直接返回该窗口。
+pub async fn launch(
+    game: &InstalledGame,
+    games: &GameWatcher,
+    wait: Option<Duration>,
+) -> Result<Launched, LaunchError> {
+    // 先订阅再启动，以免错过窗口出现的事件
+    let (windows, mut live) = games.subscribe();
+    if let Some(window) = windows.into_iter().find(|window| window.game == game.game) {
+        return Ok(Launched {
+            launched: false,
+            pid: None,
+            window: Some(window),
+        });
+    }
+    let pid = start(game).map_err(LaunchError::Failed)?;
+    let Some(wait) = wait else {
+        return Ok(Launched {
+            launched: true,
+            pid,
+            window: None,
+        });
+    };
+    let appeared = async {
+        loop {
+            match live.recv().await {
+                Ok(GameEvent::Appeared(window)) if window.game == game.game => return Some(window),
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    };
+    match tokio::time::timeout(wait, appeared).await {
+        Ok(Some(window)) => Ok(Launched {
+            launched: true,
+            pid,
+            window: Some(window),
+        }),
+        _ => Err(LaunchError::Timeout),
+    }
+}
+
+fn start(game: &InstalledGame) -> io::Result<Option<u32>> {
+    if let Some(app) = &game.epic_app {
+        let uri = format!("com.epicgames.launcher://apps/{app}?action=launch&silent=true");
+        Command::new("explorer.exe").arg(uri).spawn()?;
+        return Ok(None);
+    }
+    let mut command = Command::new(&game.exe);
+    if let Some(dir) = game.exe.parent() {
+        command.current_dir(dir);
+    }
+    Ok(Some(command.spawn()?.id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{MemoryRegistry, RegistryStore};
+    use uuid::Uuid;
+
+    fn touch(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"").unwrap();
+    }
+
+    #[test]
+    fn locates_games_from_every_source() {
+        let root = std::env::temp_dir().join(format!("frostflake-locator-{}", Uuid::new_v4()));
+        let registry = MemoryRegistry::default();
+
+        // HoYoPlay 记录的是游戏目录
+        let genshin = root.join("HoYoPlay/games/Genshin Impact game");
+        touch(&genshin.join("YuanShen.exe"));
+        registry
+            .set_string(
+                Hive::CurrentUser,
+                r"Software\miHoYo\HYP\1_1\hk4e_cn",
+                "GameInstallPath",
+                genshin.to_str().unwrap(),
+            )
+            .unwrap();
+
+        // 旧版启动器记录的是启动器目录，游戏目录在其 `config.ini` 中
+        let launcher = root.join("Star Rail");
+        let star_rail = launcher.join("Games");
+        touch(&star_rail.join("StarRail.exe"));
+        let config = format!(
+            "[launcher]\ncps=hyp_global\ngame_install_path={}\n",
+            star_rail.display()
+        );
+        fs::write(launcher.join("config.ini"), config).unwrap();
+        registry
+            .set_string(
+                Hive::LocalMachine,
+                r"SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall\Honkai: Star Rail",
+                "InstallPath",
+                launcher.to_str().unwrap(),
+            )
+            .unwrap();
+
+        // Epic 的安装目录下还有一层游戏目录
+        let epic = root.join("Epic Games/GenshinImpact");
+        touch(&epic.join("Genshin Impact Game/GenshinImpact.exe"));
+        let manifest = root.join("LauncherInstalled.dat");
+        let installed = serde_json::json!({"InstallationList": [
+            {"InstallLocation": root.join("Epic Games/Other").to_str().unwrap(), "AppName": "other"},
+            {"InstallLocation": epic.to_str().unwrap(), "AppName": "genshin-app"},
+        ]});
+        fs::write(&manifest, installed.to_string()).unwrap();
+
+        // 记录了但已被删除的游戏不会列出
+        registry
+            .set_string(
+                Hive::CurrentUser,
+                r"Software\Cognosphere\HYP\1_0\hk4e_global",
+                "GameInstallPath",
+                root.join("removed").to_str().unwrap(),
+            )
+            .unwrap();
+
+        let locator = GameLocator::with_epic_manifest(Arc::new(registry), manifest);
+        let games = locator.locate();
+        let ids: Vec<_> = games.iter().map(|game| (game.id.as_str(), game.source)).collect();
+        assert_eq!(
+            ids,
+            vec![
+                ("genshin-cn", Source::Hoyoplay),
+                ("genshin-epic", Source::Epic),
+                ("star-rail-global", Source::Launcher),
+            ]
+        );
+        assert_eq!(games[0].exe, genshin.join("YuanShen.exe"));
+        assert_eq!(games[1].exe, epic.join("Genshin Impact Game/GenshinImpact.exe"));
+        assert_eq!(games[1].epic_app.as_deref(), Some("genshin-app"));
+        assert_eq!(games[2].game, "star-rail");
+        assert_eq!(locator.find("star-rail-cn"), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,16 +1,46 @@
+pub mod ansi;
+pub mod asciicast;
+pub mod auth;
+pub mod capture;
+pub mod codec;
+#[cfg(test)]
+mod compat_tests;
+pub mod config;
+pub mod doctor;
+pub mod encoding;
+pub mod events;
+pub mod game;
+pub mod hub;
+pub mod input;
+pub mod locator;
+pub mod outbox;
+pub mod platform;
+pub mod protocol;
 pub mod registry;
+pub mod resolution;
+pub mod rpc;
+pub mod scan;
 pub mod server;
 pub mod utils;
-pub mod windows;
+pub mod window_control;
+pub mod window_filter;
+pub mod yas_args;
 
 use utils::wait_10s_exit;
 
-use crate::registry::{create_scheme_registration, get_registration_path};
-use crate::server::start_server;
+#[cfg(windows)]
+use crate::registry::{
+    command_for, detect_takeover, displace_registration, register, registrations, scheme_status, SchemeStatus,
+};
+use crate::registry::{restore_registration, uninstall_registration, RegistrationScope, Uninstalled};
+use crate::server::{start_server, Replay};
+#[cfg(windows)]
 use crate::utils::prompt_user;
 
-use std::path::Path;
-use std::{env, fs, process};
+use std::env;
+use std::path::{self, PathBuf};
+#[cfg(windows)]
+use std::{fs, process};
 
 #[tokio::main]
 async fn main() {
@@ -21,54 +51,237 @@ async fn main() {
         wait_10s_exit();
     }));
 
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("schema") {
+        let schema = serde_json::to_string_pretty(&protocol::schema()).unwrap();
+        println!("{schema}");
+        return;
+    }
+    // 回放命令中的文件路径相对于启动时的目录，需在切换目录之前解析
+    let replay = (args.first().map(String::as_str) == Some("replay")).then(|| {
+        parse_replay_args(&args[1..]).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            eprintln!("用法：frostflake-rs replay <file> [--speed <n>] [--serve]");
+            std::process::exit(2)
+        })
+    });
+
     let exe_path = env::current_exe().expect("Failed to get current executable path");
     let exe_dir = exe_path.parent().unwrap();
     env::set_current_dir(exe_dir).expect("Failed to set current directory");
     let exe_path_str = exe_path.display().to_string();
 
+    if args.first().map(String::as_str) == Some("doctor") {
+        let report = doctor::collect(config::Config::load()).await;
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return;
+    }
+
+    if let Some(command @ ("restore" | "uninstall")) = args.first().map(String::as_str) {
+        remove_registration(command == "uninstall", exe_dir);
+        return;
+    }
+
+    if let Some((replay, serve)) = replay {
+        if serve {
+            start_server("127.0.0.1:32333", Some(replay)).await;
+        } else {
+            let (_, events) = asciicast::read(&replay.file).expect("无法读取录像文件");
+            asciicast::play_in_terminal(&events, replay.speed)
+                .await
+                .expect("回放失败");
+        }
+        return;
+    }
+
     println!("当前程序路径是：`{exe_path_str}`。");
+    #[cfg(windows)]
+    ensure_registration(&exe_path_str);
+
+    start_server("127.0.0.1:32333", None).await;
+}
 
-    let need_register = match get_registration_path("cocogoat-control") {
-        Ok(hkey_path) => {
-            println!("在注册表中读取到注册信息，路径：`{hkey_path}`。");
-            hkey_path != exe_path_str
+// 确保 `cocogoat-control://` 协议指向当前程序，必要时安装到建议路径
+#[cfg(windows)]
+fn ensure_registration(exe_path_str: &str) {
+    let store = platform::registry();
+    match detect_takeover(store.as_ref(), "cocogoat-control") {
+        Ok(Some(takeover)) => println!(
+            "⚠️ 自定义协议 `cocogoat-control` 已被其他程序接管，现在的命令为 `{}`。\n如果想改回原来的处理程序，可以运行 `frostflake-rs restore`。",
+            takeover.command
+        ),
+        Ok(None) => {},
+        Err(err) => eprintln!("{err}"),
+    }
+    let need_register = match scheme_status(store.as_ref(), "cocogoat-control", exe_path_str) {
+        Ok(SchemeStatus::Current) => {
+            println!("在注册表中读取到注册信息，路径：`{exe_path_str}`。");
+            false
         },
-        Err(err) => {
+        Ok(SchemeStatus::Missing) => {
             println!("未在注册表中读取到自定义协议 `cocogoat-control`。");
+            true
+        },
+        Ok(SchemeStatus::Other { path }) => {
+            println!("在注册表中读取到注册信息，路径：`{path}`。");
+            true
+        },
+        Ok(SchemeStatus::Modified { command } | SchemeStatus::Malformed { command }) => {
+            println!("自定义协议 `cocogoat-control` 的命令 `{command}` 与本程序写入的不同。");
+            true
+        },
+        Err(err) => {
+            println!("无法读取自定义协议 `cocogoat-control` 的注册信息。");
             eprintln!("{err}");
             true
         },
     };
 
     if need_register {
-        let excepted_exe_path_str: &str = "C:\\Program Files\\frostflake-rs\\frostflake-rs.exe";
-        if excepted_exe_path_str != exe_path_str {
+        // 已经安装在某个建议路径时沿用对应的方式，否则询问
+        let installed = [RegistrationScope::User, RegistrationScope::Machine]
+            .into_iter()
+            .find(|scope| suggested_exe_path(*scope).to_string_lossy().to_lowercase() == exe_path_str.to_lowercase());
+        let scope = installed.unwrap_or_else(prompt_scope);
+        // 程序平时以当前用户的权限运行，只有为所有用户安装时才需要管理员权限
+        if scope == RegistrationScope::Machine && !platform::is_elevated() {
+            relaunch_elevated(exe_path_str);
+        }
+        if installed.is_none() {
+            let excepted_exe_path = suggested_exe_path(scope);
             println!(
-                "⚠️ 提醒：这个程序需要管理员权限喵~\n我们建议安装到路径 {excepted_exe_path_str}，这样可以更好地避免安全问题喵！"
+                "我们建议安装到路径 {}，这样可以更好地避免安全问题喵！",
+                excepted_exe_path.display()
             );
             if prompt_user("是否需要自动安装到建议路径？请输入 [Y/N] ") == "Y" {
-                let excepted_exe_path = Path::new(excepted_exe_path_str);
                 let excepted_exe_dir = excepted_exe_path.parent().unwrap();
                 if !excepted_exe_dir.exists() {
                     fs::create_dir_all(excepted_exe_dir).expect("Failed to create directory");
                 }
-                fs::copy(&exe_path_str, excepted_exe_path).expect("Failed to copy the executable");
+                fs::copy(exe_path_str, &excepted_exe_path).expect("Failed to copy the executable");
                 println!("移动完成，正在重新启动。");
-                process::Command::new(excepted_exe_path)
+                process::Command::new(&excepted_exe_path)
                     .spawn()
                     .expect("Failed to start the new process");
                 process::exit(0);
             }
         }
         if prompt_user("是否需要将注册路径更新为当前程序路径？请输入 [Y/N] ") == "Y" {
-            create_scheme_registration("cocogoat-control", &exe_path_str)
-                .expect("编辑注册表失败了喵！\n检查一下有没有开启管理员权限呀？(≧◡≦)");
+            let failed = match scope {
+                RegistrationScope::User => "编辑注册表失败了喵！",
+                RegistrationScope::Machine => "编辑注册表失败了喵！\n检查一下有没有开启管理员权限呀？(≧◡≦)",
+            };
+            let backed_up = register(store.as_ref(), "cocogoat-control", exe_path_str, scope).expect(failed);
             println!("注册表已更新。");
+            if backed_up {
+                println!("原有的注册已备份，运行 `frostflake-rs restore` 或 `frostflake-rs uninstall` 可以恢复。");
+            }
         } else {
             println!("操作已被用户取消。");
             wait_10s_exit();
         }
     }
 
-    start_server("127.0.0.1:32333").await;
+    // 当前用户的注册优先于所有用户的注册，为所有用户注册后仍可能被它遮蔽
+    if let Ok(registrations) = registrations(store.as_ref(), "cocogoat-control") {
+        if let (true, Some(command)) = (registrations.shadowed(), &registrations.user) {
+            println!("当前用户的注册 `{command}` 遮蔽了为所有用户的注册。");
+            if command != &command_for(exe_path_str)
+                && prompt_user("是否删除当前用户的注册？删除前会先备份，请输入 [Y/N] ") == "Y"
+            {
+                displace_registration(store.as_ref(), "cocogoat-control", RegistrationScope::User)
+                    .expect("编辑注册表失败了喵！");
+                println!("已删除当前用户的注册，运行 `frostflake-rs restore` 可以恢复。");
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+fn suggested_exe_path(scope: RegistrationScope) -> PathBuf {
+    scope.install_dir().join("frostflake-rs.exe")
+}
+
+#[cfg(windows)]
+fn prompt_scope() -> RegistrationScope {
+    println!("请选择安装方式：");
+    println!("  [U] 仅为当前用户安装，写入安装目录与注册表都不需要管理员权限");
+    println!("  [M] 为所有用户安装，写入安装目录与注册表需要管理员权限，将以管理员身份重新启动");
+    loop {
+        match prompt_user("请输入 [U/M] ").as_str() {
+            "U" => return RegistrationScope::User,
+            "M" => return RegistrationScope::Machine,
+            _ => continue,
+        }
+    }
+}
+
+// 以管理员身份重新启动后由新的进程继续安装，无法启动时请用户自行以管理员身份运行
+#[cfg(windows)]
+fn relaunch_elevated(exe_path_str: &str) {
+    println!("为所有用户安装需要管理员权限，正在以管理员身份重新启动，请在新的窗口中再次选择 [M]。");
+    match platform::run_elevated(path::Path::new(exe_path_str)) {
+        Ok(()) => process::exit(0),
+        Err(err) => {
+            eprintln!("{err}");
+            println!("无法以管理员身份重新启动，请右键本程序选择「以管理员身份运行」后重试。");
+            wait_10s_exit();
+        },
+    }
+}
+
+// `frostflake-rs restore` 放回本程序注册之前的协议处理程序；
+// `frostflake-rs uninstall` 同样放回，没有可放回的注册时删除本程序的注册
+fn remove_registration(uninstall: bool, exe_dir: &path::Path) {
+    let store = platform::registry();
+    for scope in [RegistrationScope::User, RegistrationScope::Machine] {
+        let name = match scope {
+            RegistrationScope::User => "当前用户",
+            RegistrationScope::Machine => "所有用户",
+        };
+        let result = match uninstall {
+            true => uninstall_registration(store.as_ref(), "cocogoat-control", scope),
+            false => restore_registration(store.as_ref(), "cocogoat-control", scope).map(|restored| match restored {
+                true => Uninstalled::Restored,
+                false => Uninstalled::NotRegistered,
+            }),
+        };
+        match result {
+            Ok(Uninstalled::Restored) => println!("已恢复{name}原有的注册。"),
+            Ok(Uninstalled::Removed) => println!("已删除{name}的注册。"),
+            Ok(Uninstalled::KeptOther) => println!("{name}的注册已被其他程序接管，予以保留。"),
+            Ok(Uninstalled::NotRegistered) if uninstall => println!("本程序没有为{name}注册过。"),
+            Ok(Uninstalled::NotRegistered) => println!("{name}没有可恢复的注册。"),
+            Err(err) => eprintln!("修改{name}的注册失败：{err}"),
+        }
+    }
+    if uninstall {
+        println!("注册已清除，可以删除程序所在的目录 `{}`。", exe_dir.display());
+    }
+}
+
+// frostflake-rs replay <file> [--speed <n>] [--serve]
+fn parse_replay_args(args: &[String]) -> Result<(Replay, bool), String> {
+    let mut file = None;
+    let mut speed = 1.0;
+    let mut serve = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--serve" => serve = true,
+            "--speed" => {
+                speed = iter
+                    .next()
+                    .and_then(|speed| speed.parse().ok())
+                    .filter(|speed: &f64| *speed > 0.0)
+                    .ok_or("--speed 需要一个正数")?;
+            },
+            _ if arg.starts_with("--") => return Err(format!("无法识别的参数 `{arg}`")),
+            _ if file.is_some() => return Err(format!("只能回放一个文件，多余的参数 `{arg}`")),
+            _ => file = Some(PathBuf::from(arg)),
+        }
+    }
+    let file = file.ok_or("缺少录像文件")?;
+    let file = path::absolute(&file).map_err(|err| format!("无法解析路径 `{}`：{err}", file.display()))?;
+    Ok((Replay { file, speed }, serve))
 }
@@ -0,0 +1,222 @@
+// 发往一个客户端的消息。应答等控制消息按顺序等待发送；yas 输出进入有界队列，
+// 客户端接收过慢时按配置丢弃，不会阻塞 yas 输出的读取。
+
+use crate::{
+    protocol::{ServerMessage, Session},
+    scan::OutputLine,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::sync::{mpsc, Notify};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    // 丢弃最早的输出，保留最新的进度
+    DropOldest,
+    // 丢弃新到达的输出
+    DropNewest,
+}
+
+pub enum Task {
+    Message(ServerMessage),
+    // 按顺序发送、不会被丢弃的输出，如重新连接时补发的输出
+    Line(OutputLine),
+    // 服务端自己的提示，同时打印到控制台
+    Note(String),
+    Negotiated(Session),
+    // JSON-RPC 的回复，原样发送
+    Reply(Value),
+}
+
+pub struct OutputQueue {
+    lines: Mutex<VecDeque<OutputLine>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    // 尚未告知客户端的丢弃行数
+    dropped: AtomicU64,
+    dropped_total: AtomicU64,
+    notify: Notify,
+}
+
+impl OutputQueue {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> OutputQueue {
+        OutputQueue {
+            lines: Mutex::new(VecDeque::new()),
+            capacity: capacity.max(1),
+            policy,
+            dropped: AtomicU64::new(0),
+            dropped_total: AtomicU64::new(0),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn push(&self, line: OutputLine) {
+        {
+            let mut lines = self.lines.lock().unwrap();
+            if lines.len() >= self.capacity {
+                self.record_dropped(1);
+                match self.policy {
+                    OverflowPolicy::DropOldest => {
+                        lines.pop_front();
+                    },
+                    OverflowPolicy::DropNewest => return,
+                }
+            }
+            lines.push_back(line);
+        }
+        self.notify.notify_one();
+    }
+
+    // 在别处已经丢失的输出，如广播通道滞后跳过的行
+    pub fn record_dropped(&self, n: u64) {
+        self.dropped.fetch_add(n, Ordering::Relaxed);
+        self.dropped_total.fetch_add(n, Ordering::Relaxed);
+        self.notify.notify_one();
+    }
+
+    pub fn pop(&self) -> Option<OutputLine> {
+        self.lines.lock().unwrap().pop_front()
+    }
+
+    // 返回自上次调用以来丢弃的行数与连接建立以来的总数
+    pub fn take_dropped(&self) -> Option<(u64, u64)> {
+        match self.dropped.swap(0, Ordering::Relaxed) {
+            0 => None,
+            n => Some((n, self.dropped_total.load(Ordering::Relaxed))),
+        }
+    }
+
+    // 等待队列中有输出或有需要告知的丢弃
+    pub async fn ready(&self) {
+        if !self.lines.lock().unwrap().is_empty() || self.dropped.load(Ordering::Relaxed) > 0 {
+            return;
+        }
+        self.notify.notified().await;
+    }
+}
+
+// 连接关闭后发送失败的消息直接丢弃
+#[derive(Clone)]
+pub struct Outbox {
+    control: mpsc::Sender<Task>,
+    pub output: Arc<OutputQueue>,
+}
+
+impl Outbox {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> (Outbox, mpsc::Receiver<Task>) {
+        let (control, rx) = mpsc::channel(32);
+        let output = Arc::new(OutputQueue::new(capacity, policy));
+        (Outbox { control, output }, rx)
+    }
+
+    pub async fn send(&self, task: Task) {
+        let _ = self.control.send(task).await;
+    }
+
+    pub async fn message(&self, message: ServerMessage) {
+        self.send(Task::Message(message)).await;
+    }
+
+    pub fn line(&self, line: OutputLine) {
+        self.output.push(line);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.control.is_closed()
+    }
+
+    // 发送端结束（客户端断开或发送失败）时返回
+    pub async fn closed(&self) {
+        self.control.closed().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::Stream;
+    use std::time::Duration;
+
+    fn line(seq: u64) -> OutputLine {
+        OutputLine {
+            seq,
+            time: String::new(),
+            stream: Stream::Stdout,
+            text: seq.to_string(),
+        }
+    }
+
+    fn drain(queue: &OutputQueue) -> Vec<u64> {
+        std::iter::from_fn(|| queue.pop()).map(|line| line.seq).collect()
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_latest_lines() {
+        let queue = OutputQueue::new(2, OverflowPolicy::DropOldest);
+        (0..5).for_each(|seq| queue.push(line(seq)));
+        assert_eq!(drain(&queue), [3, 4]);
+        assert_eq!(queue.take_dropped(), Some((3, 3)));
+        assert_eq!(queue.take_dropped(), None);
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_first_lines() {
+        let queue = OutputQueue::new(2, OverflowPolicy::DropNewest);
+        (0..5).for_each(|seq| queue.push(line(seq)));
+        assert_eq!(drain(&queue), [0, 1]);
+        assert_eq!(queue.take_dropped(), Some((3, 3)));
+    }
+
+    #[tokio::test]
+    async fn dropped_counters_accumulate() {
+        let queue = OutputQueue::new(1, OverflowPolicy::DropOldest);
+        queue.push(line(0));
+        queue.push(line(1));
+        assert_eq!(queue.take_dropped(), Some((1, 1)));
+        // 广播通道滞后跳过的行同样计入，队列为空时也会唤醒发送端
+        drain(&queue);
+        queue.record_dropped(4);
+        tokio::time::timeout(Duration::from_secs(1), queue.ready())
+            .await
+            .unwrap();
+        assert_eq!(queue.take_dropped(), Some((4, 5)));
+    }
+
+    #[tokio::test]
+    async fn full_control_channel_does_not_block_output() {
+        let (outbox, mut rx) = Outbox::new(4, OverflowPolicy::DropOldest);
+        for i in 0..32 {
+            outbox.send(Task::Note(i.to_string())).await;
+        }
+        // 控制消息等待发送，yas 输出仍然可以进入队列
+        let pending = tokio::spawn({
+            let outbox = outbox.clone();
+            async move { outbox.send(Task::Note("last".to_owned())).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!pending.is_finished());
+        outbox.line(line(0));
+        assert_eq!(drain(&outbox.output), [0]);
+
+        assert!(matches!(rx.recv().await, Some(Task::Note(note)) if note == "0"));
+        tokio::time::timeout(Duration::from_secs(1), pending)
+            .await
+            .unwrap()
+            .unwrap();
+        // 连接关闭后发送直接返回
+        drop(rx);
+        assert!(outbox.is_closed());
+        tokio::time::timeout(Duration::from_secs(1), outbox.send(Task::Note("closed".to_owned())))
+            .await
+            .unwrap();
+    }
+}
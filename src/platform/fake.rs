@@ -0,0 +1,256 @@
+// 保存在内存中的窗口管理器，操作只修改记录的窗口位置与状态；按坐标生成图案的截图；只记录事件的模拟输入；
+// 保存在内存中的注册表。
+// 非 Windows 平台以它们代替系统的实现，测试中也可以用任意的窗口构造。
+
+use super::{
+    Capture, DisplayInfo, Frame, Hive, InputEvent, InputInjector, Rect, RegistryStore, Size, WindowInfo, WindowManager,
+};
+
+use std::{
+    collections::BTreeMap,
+    io::{Error, ErrorKind, Result},
+    sync::Mutex,
+};
+
+pub struct FakeWindowManager {
+    // 按 Z 序排列，最上层的窗口在最前
+    windows: Mutex<Vec<WindowInfo>>,
+    displays: Vec<DisplayInfo>,
+}
+
+impl FakeWindowManager {
+    pub fn new(windows: Vec<WindowInfo>, displays: Vec<DisplayInfo>) -> FakeWindowManager {
+        FakeWindowManager {
+            windows: Mutex::new(windows),
+            displays,
+        }
+    }
+
+    fn with_window(&self, hwnd: usize, f: impl FnOnce(&mut Vec<WindowInfo>, usize)) -> Result<()> {
+        let mut windows = self.windows.lock().unwrap();
+        let index = windows
+            .iter()
+            .position(|window| window.hwnd == hwnd)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("no window {hwnd}")))?;
+        f(&mut windows, index);
+        Ok(())
+    }
+
+    fn raise(windows: &mut Vec<WindowInfo>, index: usize) {
+        let window = windows.remove(index);
+        windows.insert(0, window);
+    }
+}
+
+impl WindowManager for FakeWindowManager {
+    fn list(&self) -> Result<Vec<WindowInfo>> {
+        Ok(self.windows.lock().unwrap().clone())
+    }
+
+    fn displays(&self) -> Result<Vec<DisplayInfo>> {
+        Ok(self.displays.clone())
+    }
+
+    fn focus(&self, hwnd: usize) -> Result<()> {
+        self.with_window(hwnd, |windows, index| {
+            windows[index].minimized = false;
+            Self::raise(windows, index);
+        })
+    }
+
+    fn restore(&self, hwnd: usize) -> Result<()> {
+        self.with_window(hwnd, |windows, index| {
+            let window = &mut windows[index];
+            window.minimized = false;
+            window.maximized = false;
+            window.visible = true;
+        })
+    }
+
+    fn minimize(&self, hwnd: usize) -> Result<()> {
+        self.with_window(hwnd, |windows, index| windows[index].minimized = true)
+    }
+
+    fn bring_to_top(&self, hwnd: usize) -> Result<()> {
+        self.with_window(hwnd, Self::raise)
+    }
+
+    fn move_to(&self, hwnd: usize, x: i32, y: i32) -> Result<()> {
+        self.with_window(hwnd, |windows, index| {
+            let window = &mut windows[index];
+            window.client.x += x - window.x;
+            window.client.y += y - window.y;
+            (window.x, window.y) = (x, y);
+        })
+    }
+
+    fn resize_client(&self, hwnd: usize, width: i32, height: i32) -> Result<()> {
+        self.with_window(hwnd, |windows, index| {
+            let window = &mut windows[index];
+            window.width += width - window.client.width;
+            window.height += height - window.client.height;
+            (window.client.width, window.client.height) = (width, height);
+            window.logical_client = Size::logical(width, height, window.scale);
+        })
+    }
+}
+
+// 每个像素的颜色只取决于它的屏幕坐标：红色随 x、绿色随 y 变化，蓝色为 64 像素的棋盘格，
+// 截取同一区域总是得到相同的图像，便于检查裁剪与缩放
+pub struct SyntheticCapture;
+
+impl SyntheticCapture {
+    pub fn pixel(x: i32, y: i32) -> [u8; 3] {
+        let checker = if (x.div_euclid(64) + y.div_euclid(64)) % 2 == 0 {
+            0
+        } else {
+            255
+        };
+        [x.rem_euclid(256) as u8, y.rem_euclid(256) as u8, checker]
+    }
+}
+
+impl Capture for SyntheticCapture {
+    fn capture_screen(&self, region: Rect) -> Result<Frame> {
+        if region.width <= 0 || region.height <= 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "empty region"));
+        }
+        let mut pixels = Vec::with_capacity(region.width as usize * region.height as usize * 3);
+        for y in region.y..region.y + region.height {
+            for x in region.x..region.x + region.width {
+                pixels.extend_from_slice(&Self::pixel(x, y));
+            }
+        }
+        Ok(Frame {
+            width: region.width as u32,
+            height: region.height as u32,
+            pixels,
+        })
+    }
+}
+
+// 记录发送的输入事件，`hold` 模拟用户按住某些键
+#[derive(Default)]
+pub struct RecordingInjector {
+    events: Mutex<Vec<InputEvent>>,
+    held: Mutex<Vec<u16>>,
+}
+
+impl RecordingInjector {
+    pub fn events(&self) -> Vec<InputEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    pub fn hold(&self, keys: &[u16]) {
+        *self.held.lock().unwrap() = keys.to_vec();
+    }
+}
+
+impl InputInjector for RecordingInjector {
+    fn send(&self, event: InputEvent) -> Result<()> {
+        self.events.lock().unwrap().push(event);
+        Ok(())
+    }
+
+    fn pressed(&self, keys: &[u16]) -> bool {
+        let held = self.held.lock().unwrap();
+        !keys.is_empty() && keys.iter().all(|key| held.contains(key))
+    }
+}
+
+// 与系统的注册表一样，键名与值名不区分大小写，但保留写入时的大小写
+#[derive(Default)]
+pub struct MemoryRegistry {
+    // 以小写的路径为键
+    keys: Mutex<BTreeMap<(Hive, String), MemoryKey>>,
+}
+
+#[derive(Default)]
+struct MemoryKey {
+    // 创建时的路径
+    path: String,
+    // 以小写的值名为键，保存原来的值名与值
+    values: BTreeMap<String, (String, String)>,
+}
+
+impl MemoryRegistry {
+    fn key(hive: Hive, path: &str) -> (Hive, String) {
+        (hive, path.trim_matches('\\').to_lowercase())
+    }
+
+    // 与系统的注册表一样，上级键也一起创建
+    fn create(keys: &mut BTreeMap<(Hive, String), MemoryKey>, hive: Hive, path: &str) -> (Hive, String) {
+        let path = path.trim_matches('\\');
+        let ends = path.match_indices('\\').map(|(end, _)| end).chain([path.len()]);
+        for end in ends {
+            keys.entry(MemoryRegistry::key(hive, &path[..end]))
+                .or_insert_with(|| MemoryKey {
+                    path: path[..end].to_owned(),
+                    values: BTreeMap::new(),
+                });
+        }
+        MemoryRegistry::key(hive, path)
+    }
+}
+
+impl RegistryStore for MemoryRegistry {
+    fn get_string(&self, hive: Hive, path: &str, name: &str) -> Result<Option<String>> {
+        let keys = self.keys.lock().unwrap();
+        let value = keys
+            .get(&MemoryRegistry::key(hive, path))
+            .and_then(|key| key.values.get(&name.to_lowercase()))
+            .map(|(_, value)| value.clone());
+        Ok(value)
+    }
+
+    fn key_exists(&self, hive: Hive, path: &str) -> Result<bool> {
+        Ok(self.keys.lock().unwrap().contains_key(&MemoryRegistry::key(hive, path)))
+    }
+
+    fn subkeys(&self, hive: Hive, path: &str) -> Result<Vec<String>> {
+        let (hive, path) = MemoryRegistry::key(hive, path);
+        let prefix = format!("{path}\\");
+        let keys = self.keys.lock().unwrap();
+        let subkeys = keys
+            .iter()
+            .filter(|((key_hive, key), _)| *key_hive == hive && key.starts_with(&prefix))
+            .filter(|((_, key), _)| !key[prefix.len()..].contains('\\'))
+            .map(|(_, key)| key.path[prefix.len()..].to_owned())
+            .collect();
+        Ok(subkeys)
+    }
+
+    fn values(&self, hive: Hive, path: &str) -> Result<Vec<(String, String)>> {
+        let keys = self.keys.lock().unwrap();
+        let values = keys
+            .get(&MemoryRegistry::key(hive, path))
+            .map(|key| key.values.values().cloned().collect())
+            .unwrap_or_default();
+        Ok(values)
+    }
+
+    fn create_key(&self, hive: Hive, path: &str) -> Result<()> {
+        MemoryRegistry::create(&mut self.keys.lock().unwrap(), hive, path);
+        Ok(())
+    }
+
+    fn set_string(&self, hive: Hive, path: &str, name: &str, value: &str) -> Result<()> {
+        let mut keys = self.keys.lock().unwrap();
+        let key = MemoryRegistry::create(&mut keys, hive, path);
+        keys.get_mut(&key)
+            .unwrap()
+            .values
+            .insert(name.to_lowercase(), (name.to_owned(), value.to_owned()));
+        Ok(())
+    }
+
+    fn delete_tree(&self, hive: Hive, path: &str) -> Result<()> {
+        let (hive, path) = MemoryRegistry::key(hive, path);
+        let prefix = format!("{path}\\");
+        self.keys
+            .lock()
+            .unwrap()
+            .retain(|(key_hive, key), _| *key_hive != hive || (key != &path && !key.starts_with(&prefix)));
+        Ok(())
+    }
+}
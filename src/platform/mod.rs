@@ -0,0 +1,200 @@
+// 与操作系统相关的功能。frostflake-rs 只在 Windows 上发布，其他平台上的实现仅用于开发与测试。
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::Result;
+
+#[cfg(any(test, not(windows)))]
+mod fake;
+#[cfg(not(windows))]
+mod stub;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(any(test, not(windows)))]
+pub use self::fake::{FakeWindowManager, MemoryRegistry, RecordingInjector, SyntheticCapture};
+#[cfg(not(windows))]
+pub use self::stub::*;
+#[cfg(windows)]
+pub use self::windows::*;
+
+// 窗口的查询与操作。Windows 上为系统的实现，其他平台与测试中为保存在内存中的假实现，
+// 通过 `window_manager()` 取得当前平台的实现
+pub trait WindowManager: Send + Sync {
+    fn list(&self) -> Result<Vec<WindowInfo>>;
+    fn displays(&self) -> Result<Vec<DisplayInfo>>;
+    // 切换到前台并获得焦点
+    fn focus(&self, hwnd: usize) -> Result<()>;
+    // 从最小化或最大化恢复
+    fn restore(&self, hwnd: usize) -> Result<()>;
+    fn minimize(&self, hwnd: usize) -> Result<()>;
+    // 移到其他窗口之上，但不获取焦点
+    fn bring_to_top(&self, hwnd: usize) -> Result<()>;
+    // 移动窗口，使其左上角位于屏幕坐标 `(x, y)`
+    fn move_to(&self, hwnd: usize, x: i32, y: i32) -> Result<()>;
+    // 调整窗口大小，使客户区为 `width × height`，边框与标题栏另算
+    fn resize_client(&self, hwnd: usize, width: i32, height: i32) -> Result<()>;
+}
+
+// 截图。Windows 上以 GDI 从屏幕复制，其他平台与测试中为按坐标生成的图案，
+// 通过 `capture()` 取得当前平台的实现
+// 截取窗口即截取屏幕上其客户区所在的区域，窗口被遮挡时会截到遮挡它的窗口
+pub trait Capture: Send + Sync {
+    // 截取屏幕上的一块区域，坐标为物理像素
+    fn capture_screen(&self, region: Rect) -> Result<Frame>;
+}
+
+// 模拟鼠标与键盘输入。Windows 上以 `SendInput` 发送，其他平台与测试中只记录发送的事件，
+// 通过 `input_injector()` 取得当前平台的实现
+pub trait InputInjector: Send + Sync {
+    fn send(&self, event: InputEvent) -> Result<()>;
+    // 这些键是否都处于按下状态，用于检测紧急停止的热键
+    fn pressed(&self, keys: &[u16]) -> bool;
+}
+
+// 注册表的读写。Windows 上为系统的注册表，其他平台与测试中为保存在内存中的假实现，
+// 通过 `registry()` 取得当前平台的实现。键名与值名不区分大小写，`name` 为空时即默认值
+pub trait RegistryStore: Send + Sync {
+    // 键或值不存在时返回 `None`，值不是字符串时返回错误
+    fn get_string(&self, hive: Hive, path: &str, name: &str) -> Result<Option<String>>;
+    fn key_exists(&self, hive: Hive, path: &str) -> Result<bool>;
+    // 直接子键的名称，键不存在时为空
+    fn subkeys(&self, hive: Hive, path: &str) -> Result<Vec<String>>;
+    // 键中的字符串值，其他类型的值被忽略，键不存在时为空
+    fn values(&self, hive: Hive, path: &str) -> Result<Vec<(String, String)>>;
+    // 键不存在时连同上级一起创建
+    fn create_key(&self, hive: Hive, path: &str) -> Result<()>;
+    // 键不存在时先创建
+    fn set_string(&self, hive: Hive, path: &str, name: &str, value: &str) -> Result<()>;
+    // 删除键及其所有子键，键不存在时什么也不做
+    fn delete_tree(&self, hive: Hive, path: &str) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Hive {
+    CurrentUser,
+    LocalMachine,
+}
+
+// 一个输入事件，坐标为屏幕上的物理像素，按键为 Windows 的虚拟键码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    MouseMove { x: i32, y: i32 },
+    MouseButton { button: MouseButton, down: bool },
+    // 滚轮的格数，正数向上
+    Scroll { notches: i32 },
+    Key { code: u16, down: bool },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MouseButton {
+    #[default]
+    Left,
+    Right,
+    Middle,
+}
+
+// 一帧图像，像素为按行排列的 RGB
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowInfo {
+    pub class_name: String,
+    pub title: String,
+    pub hwnd: usize,
+    pub height: i32,
+    pub width: i32,
+    pub x: i32,
+    pub y: i32,
+    pub pid: u32,
+    // 可执行文件名，如 `YuanShen.exe`，无权查询该进程时为空
+    pub process: String,
+    pub visible: bool,
+    pub minimized: bool,
+    pub maximized: bool,
+    // 窗口所在（或最靠近）的显示器，与 `DisplayInfo::name` 对应
+    pub monitor: String,
+    // 客户区在屏幕上的位置与大小，不含标题栏与边框
+    pub client: Rect,
+    // 窗口所在显示器的 DPI 与缩放比例
+    pub dpi: u32,
+    pub scale: f64,
+    // 客户区的逻辑尺寸，即物理像素除以缩放比例，未声明支持高 DPI 的程序看到的大小
+    pub logical_client: Size,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Size {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Size {
+    // 物理像素换算为逻辑像素
+    pub fn logical(width: i32, height: i32, scale: f64) -> Size {
+        Size {
+            width: (width as f64 / scale).round() as i32,
+            height: (height as f64 / scale).round() as i32,
+        }
+    }
+}
+
+// Windows 11 22H2 起提供“窗口化游戏优化”，即将窗口化游戏的交换链升级为翻转模型
+pub const SWAP_EFFECT_UPGRADE_MIN_BUILD: u32 = 22621;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemInfo {
+    pub major: u32,
+    pub minor: u32,
+    pub build: u32,
+    // 累积更新的修订号（UBR）
+    pub revision: u32,
+    // 本程序控制台窗口的句柄，没有控制台时为 0
+    pub console_hwnd: usize,
+    // 系统支持并已开启窗口化游戏优化，开启后部分截图方式只能得到黑屏
+    pub swap_effect_upgrade: bool,
+}
+
+impl SystemInfo {
+    // 面向用户的 Windows 版本号，Windows 11 的主版本号仍为 10，只能通过构建号区分
+    pub fn winver(&self) -> u32 {
+        match (self.major, self.build) {
+            (10, 22000..) => 11,
+            (major, _) => major,
+        }
+    }
+
+    pub fn version(&self) -> String {
+        format!("{}.{}.{}.{}", self.major, self.minor, self.build, self.revision)
+    }
+}
+
+// 一台显示器，坐标与尺寸为物理像素
+#[derive(Debug, Clone, Serialize)]
+pub struct DisplayInfo {
+    pub name: String,
+    pub primary: bool,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    // 除去任务栏等之后的工作区
+    pub work_area: Rect,
+    pub dpi: u32,
+    // 缩放比例，`dpi / 96`
+    pub scale: f64,
+}
@@ -0,0 +1,125 @@
+// 没有窗口与系统通知的平台，返回固定的窗口与系统信息，其余操作什么也不做
+use super::{
+    Capture, DisplayInfo, FakeWindowManager, InputInjector, MemoryRegistry, RecordingInjector, Rect, RegistryStore,
+    Size, SyntheticCapture, SystemInfo, WindowInfo, WindowManager,
+};
+
+use std::{io::Result, path::Path, sync::Arc};
+
+// 固定的两个窗口：全屏的原神与一个普通窗口，便于测试窗口的过滤
+pub fn list_windows() -> Result<Vec<WindowInfo>> {
+    let window = |hwnd, title: &str, class_name: &str, pid, process: &str, (x, y, width, height)| WindowInfo {
+        class_name: class_name.to_owned(),
+        title: title.to_owned(),
+        hwnd,
+        height,
+        width,
+        x,
+        y,
+        pid,
+        process: process.to_owned(),
+        visible: true,
+        minimized: false,
+        maximized: false,
+        monitor: "stub".to_owned(),
+        client: Rect { x, y, width, height },
+        dpi: 96,
+        scale: 1.0,
+        logical_client: Size { width, height },
+    };
+    Ok(vec![
+        window(
+            0x10010,
+            "原神",
+            "UnityWndClass",
+            1000,
+            "YuanShen.exe",
+            (0, 0, 1920, 1080),
+        ),
+        window(
+            0x20020,
+            "无标题 - 记事本",
+            "Notepad",
+            2000,
+            "notepad.exe",
+            (100, 100, 800, 600),
+        ),
+    ])
+}
+
+pub fn enable_virtual_terminal_sequences() -> Result<()> {
+    Ok(())
+}
+
+pub fn active_console_window() -> Result<()> {
+    Ok(())
+}
+
+pub fn notify_message(title: &str, message: &str) -> Result<()> {
+    println!("[{title}] {message}");
+    Ok(())
+}
+
+// 固定返回一台开启了窗口化游戏优化的 Windows 11 23H2，便于测试得到稳定的结果
+pub fn system_info() -> SystemInfo {
+    SystemInfo {
+        major: 10,
+        minor: 0,
+        build: 22631,
+        revision: 0,
+        console_hwnd: 0,
+        swap_effect_upgrade: true,
+    }
+}
+
+// 以固定的窗口与显示器为初始状态的假实现，窗口操作只改变记录的状态
+pub fn window_manager() -> Arc<dyn WindowManager> {
+    Arc::new(FakeWindowManager::new(
+        list_windows().unwrap(),
+        list_displays().unwrap(),
+    ))
+}
+
+// 按坐标生成的图案，代替屏幕上的内容
+pub fn capture() -> Arc<dyn Capture> {
+    Arc::new(SyntheticCapture)
+}
+
+// 只记录而不发送输入事件
+pub fn input_injector() -> Arc<dyn InputInjector> {
+    Arc::new(RecordingInjector::default())
+}
+
+// 空的注册表，找不到任何已安装的游戏，协议的注册只在进程内有效
+pub fn registry() -> Arc<dyn RegistryStore> {
+    Arc::new(MemoryRegistry::default())
+}
+
+// 一台 100% 缩放的 1080p 显示器
+pub fn list_displays() -> Result<Vec<DisplayInfo>> {
+    Ok(vec![DisplayInfo {
+        name: "stub".to_owned(),
+        primary: true,
+        x: 0,
+        y: 0,
+        width: 1920,
+        height: 1080,
+        // 任务栏高 40 像素
+        work_area: Rect {
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1040,
+        },
+        dpi: 96,
+        scale: 1.0,
+    }])
+}
+
+pub fn free_disk_space(_path: &Path) -> Result<u64> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+pub fn system_proxy() -> Option<String> {
+    None
+}
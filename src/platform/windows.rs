@@ -0,0 +1,653 @@
+use super::{
+    Capture, DisplayInfo, Frame, Hive, InputEvent, InputInjector, MouseButton, Rect, RegistryStore, Size, SystemInfo,
+    WindowInfo, WindowManager, SWAP_EFFECT_UPGRADE_MIN_BUILD,
+};
+
+use std::{path::Path, sync::Arc};
+use windows::{
+    core::{Error, Result, BOOL, HSTRING, PCWSTR, PWSTR},
+    Win32::{
+        Foundation::{CloseHandle, HWND, LPARAM, POINT, RECT, S_OK},
+        Graphics::Gdi::{
+            BitBlt, ClientToScreen, CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject,
+            EnumDisplayMonitors, GetDC, GetDIBits, GetMonitorInfoW, MonitorFromWindow, ReleaseDC, SelectObject,
+            BITMAPINFO, BITMAPINFOHEADER, BI_RGB, CAPTUREBLT, DIB_RGB_COLORS, HDC, HMONITOR, MONITORINFO,
+            MONITORINFOEXW, MONITOR_DEFAULTTONEAREST, SRCCOPY,
+        },
+        Storage::FileSystem::GetDiskFreeSpaceExW,
+        System::{
+            Console::{
+                GetConsoleMode, GetConsoleWindow, GetStdHandle, SetConsoleMode, CONSOLE_MODE,
+                ENABLE_VIRTUAL_TERMINAL_PROCESSING, STD_OUTPUT_HANDLE,
+            },
+            Threading::{
+                OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+            },
+        },
+        UI::{
+            HiDpi::{GetDpiForMonitor, GetDpiForWindow, MDT_EFFECTIVE_DPI},
+            Input::KeyboardAndMouse::{
+                GetAsyncKeyState, MapVirtualKeyW, SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, INPUT_MOUSE, KEYBDINPUT,
+                KEYBD_EVENT_FLAGS, KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP, MAPVK_VK_TO_VSC, MOUSEEVENTF_ABSOLUTE,
+                MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP,
+                MOUSEEVENTF_MOVE, MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_VIRTUALDESK,
+                MOUSEEVENTF_WHEEL, MOUSEINPUT, MOUSE_EVENT_FLAGS, VIRTUAL_KEY,
+            },
+            Shell::{IsUserAnAdmin, ShellExecuteW},
+            WindowsAndMessaging::{
+                BringWindowToTop, EnumWindows, GetClassNameW, GetClientRect, GetSystemMetrics, GetWindowRect,
+                GetWindowTextW, GetWindowThreadProcessId, IsIconic, IsWindowVisible, IsZoomed, SetForegroundWindow,
+                SetWindowPos, ShowWindow, MONITORINFOF_PRIMARY, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN,
+                SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN, SWP_NOACTIVATE, SWP_NOMOVE, SWP_NOSIZE, SWP_NOZORDER,
+                SW_MINIMIZE, SW_RESTORE, SW_SHOWNORMAL, WHEEL_DELTA,
+            },
+        },
+    },
+    UI::Notifications::{ToastNotification, ToastNotificationManager, ToastTemplateType},
+};
+use winreg::{
+    enums::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE},
+    types::FromRegValue,
+    RegKey,
+};
+
+// 列出所有窗口的安全接口
+pub fn list_windows() -> Result<Vec<WindowInfo>> {
+    let mut windows = Vec::new();
+
+    // 调用 `EnumWindows`，内部通过回调函数枚举窗口
+    unsafe {
+        EnumWindows(Some(enum_windows_callback), LPARAM(&mut windows as *mut _ as _))?;
+    }
+
+    Ok(windows)
+}
+
+pub fn window_manager() -> Arc<dyn WindowManager> {
+    Arc::new(SystemWindowManager)
+}
+
+pub struct SystemWindowManager;
+
+impl WindowManager for SystemWindowManager {
+    fn list(&self) -> std::io::Result<Vec<WindowInfo>> {
+        Ok(list_windows()?)
+    }
+
+    fn displays(&self) -> std::io::Result<Vec<DisplayInfo>> {
+        Ok(list_displays()?)
+    }
+
+    fn focus(&self, hwnd: usize) -> std::io::Result<()> {
+        unsafe { Ok(SetForegroundWindow(to_hwnd(hwnd)).ok()?) }
+    }
+
+    fn restore(&self, hwnd: usize) -> std::io::Result<()> {
+        // 返回值表示之前是否可见，不代表成功与否
+        unsafe {
+            let _ = ShowWindow(to_hwnd(hwnd), SW_RESTORE);
+        }
+        Ok(())
+    }
+
+    fn minimize(&self, hwnd: usize) -> std::io::Result<()> {
+        unsafe {
+            let _ = ShowWindow(to_hwnd(hwnd), SW_MINIMIZE);
+        }
+        Ok(())
+    }
+
+    fn bring_to_top(&self, hwnd: usize) -> std::io::Result<()> {
+        unsafe { Ok(BringWindowToTop(to_hwnd(hwnd))?) }
+    }
+
+    fn move_to(&self, hwnd: usize, x: i32, y: i32) -> std::io::Result<()> {
+        let flags = SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE;
+        unsafe { Ok(SetWindowPos(to_hwnd(hwnd), None, x, y, 0, 0, flags)?) }
+    }
+
+    // 当前窗口与客户区的尺寸之差即为边框与标题栏，不必再按窗口样式与 DPI 计算
+    fn resize_client(&self, hwnd: usize, width: i32, height: i32) -> std::io::Result<()> {
+        let hwnd = to_hwnd(hwnd);
+        let (mut window, mut client) = (RECT::default(), RECT::default());
+        unsafe {
+            GetWindowRect(hwnd, &mut window)?;
+            GetClientRect(hwnd, &mut client)?;
+        }
+        let frame_width = (window.right - window.left) - (client.right - client.left);
+        let frame_height = (window.bottom - window.top) - (client.bottom - client.top);
+        let flags = SWP_NOMOVE | SWP_NOZORDER | SWP_NOACTIVATE;
+        unsafe {
+            Ok(SetWindowPos(
+                hwnd,
+                None,
+                0,
+                0,
+                width + frame_width,
+                height + frame_height,
+                flags,
+            )?)
+        }
+    }
+}
+
+fn to_hwnd(hwnd: usize) -> HWND {
+    HWND(hwnd as *mut _)
+}
+
+pub fn input_injector() -> Arc<dyn InputInjector> {
+    Arc::new(SystemInputInjector)
+}
+
+pub struct SystemInputInjector;
+
+impl InputInjector for SystemInputInjector {
+    fn send(&self, event: InputEvent) -> std::io::Result<()> {
+        let input = match event {
+            // 绝对坐标需要换算到虚拟桌面上的 0 到 65535
+            InputEvent::MouseMove { x, y } => unsafe {
+                let (left, top) = (GetSystemMetrics(SM_XVIRTUALSCREEN), GetSystemMetrics(SM_YVIRTUALSCREEN));
+                let width = GetSystemMetrics(SM_CXVIRTUALSCREEN).max(2);
+                let height = GetSystemMetrics(SM_CYVIRTUALSCREEN).max(2);
+                mouse_input(
+                    (x - left) * 65535 / (width - 1),
+                    (y - top) * 65535 / (height - 1),
+                    0,
+                    MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE | MOUSEEVENTF_VIRTUALDESK,
+                )
+            },
+            InputEvent::MouseButton { button, down } => {
+                let flags = match (button, down) {
+                    (MouseButton::Left, true) => MOUSEEVENTF_LEFTDOWN,
+                    (MouseButton::Left, false) => MOUSEEVENTF_LEFTUP,
+                    (MouseButton::Right, true) => MOUSEEVENTF_RIGHTDOWN,
+                    (MouseButton::Right, false) => MOUSEEVENTF_RIGHTUP,
+                    (MouseButton::Middle, true) => MOUSEEVENTF_MIDDLEDOWN,
+                    (MouseButton::Middle, false) => MOUSEEVENTF_MIDDLEUP,
+                };
+                mouse_input(0, 0, 0, flags)
+            },
+            InputEvent::Scroll { notches } => {
+                mouse_input(0, 0, (notches * WHEEL_DELTA as i32) as u32, MOUSEEVENTF_WHEEL)
+            },
+            InputEvent::Key { code, down } => {
+                let mut flags = KEYBD_EVENT_FLAGS(0);
+                // 方向键与 Insert、Delete、Home、End、Page Up、Page Down 需要标记为扩展键
+                if matches!(code, 0x21..=0x28 | 0x2d | 0x2e) {
+                    flags |= KEYEVENTF_EXTENDEDKEY;
+                }
+                if !down {
+                    flags |= KEYEVENTF_KEYUP;
+                }
+                INPUT {
+                    r#type: INPUT_KEYBOARD,
+                    Anonymous: INPUT_0 {
+                        ki: KEYBDINPUT {
+                            wVk: VIRTUAL_KEY(code),
+                            wScan: unsafe { MapVirtualKeyW(code as u32, MAPVK_VK_TO_VSC) } as u16,
+                            dwFlags: flags,
+                            time: 0,
+                            dwExtraInfo: 0,
+                        },
+                    },
+                }
+            },
+        };
+        // 返回值为成功插入的事件数，被 UIPI 拦截时为 0
+        let sent = unsafe { SendInput(&[input], std::mem::size_of::<INPUT>() as i32) };
+        if sent == 0 {
+            return Err(Error::from_thread().into());
+        }
+        Ok(())
+    }
+
+    fn pressed(&self, keys: &[u16]) -> bool {
+        // 最高位表示当前处于按下状态
+        !keys.is_empty() && keys.iter().all(|&key| unsafe { GetAsyncKeyState(key as i32) } < 0)
+    }
+}
+
+fn mouse_input(dx: i32, dy: i32, data: u32, flags: MOUSE_EVENT_FLAGS) -> INPUT {
+    INPUT {
+        r#type: INPUT_MOUSE,
+        Anonymous: INPUT_0 {
+            mi: MOUSEINPUT {
+                dx,
+                dy,
+                mouseData: data,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    }
+}
+
+pub fn capture() -> Arc<dyn Capture> {
+    Arc::new(GdiCapture)
+}
+
+// 以 GDI 从屏幕复制。开启窗口化游戏优化后 `PrintWindow` 只能截到黑屏，从屏幕复制则不受影响，
+// 但需要游戏窗口没有被遮挡
+pub struct GdiCapture;
+
+impl Capture for GdiCapture {
+    fn capture_screen(&self, region: Rect) -> std::io::Result<Frame> {
+        if region.width <= 0 || region.height <= 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "empty region"));
+        }
+        let (width, height) = (region.width, region.height);
+        let mut bgra = vec![0u8; width as usize * height as usize * 4];
+        unsafe {
+            let screen = GetDC(None);
+            let memory = CreateCompatibleDC(Some(screen));
+            let bitmap = CreateCompatibleBitmap(screen, width, height);
+            let previous = SelectObject(memory, bitmap.into());
+            let copied = BitBlt(
+                memory,
+                0,
+                0,
+                width,
+                height,
+                Some(screen),
+                region.x,
+                region.y,
+                SRCCOPY | CAPTUREBLT,
+            );
+            // 位图仍选入在设备上下文中时不能用 `GetDIBits` 读取，先换回原来的对象
+            SelectObject(memory, previous);
+            // 高度为负表示自上而下排列
+            let mut info = BITMAPINFO {
+                bmiHeader: BITMAPINFOHEADER {
+                    biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                    biWidth: width,
+                    biHeight: -height,
+                    biPlanes: 1,
+                    biBitCount: 32,
+                    biCompression: BI_RGB.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let lines = GetDIBits(
+                memory,
+                bitmap,
+                0,
+                height as u32,
+                Some(bgra.as_mut_ptr().cast()),
+                &mut info,
+                DIB_RGB_COLORS,
+            );
+            let error = Error::from_thread();
+            let _ = DeleteObject(bitmap.into());
+            let _ = DeleteDC(memory);
+            ReleaseDC(None, screen);
+            copied?;
+            if lines == 0 {
+                return Err(error.into());
+            }
+        }
+        let pixels = bgra
+            .chunks_exact(4)
+            .flat_map(|pixel| [pixel[2], pixel[1], pixel[0]])
+            .collect();
+        Ok(Frame {
+            width: width as u32,
+            height: height as u32,
+            pixels,
+        })
+    }
+}
+
+pub fn enable_virtual_terminal_sequences() -> Result<()> {
+    unsafe {
+        // 获取标准输出句柄
+        let handle = GetStdHandle(STD_OUTPUT_HANDLE)?;
+        let mut mode: CONSOLE_MODE = CONSOLE_MODE(0);
+        GetConsoleMode(handle, &mut mode as *mut _)?;
+        // 启用虚拟终端处理，支持彩色
+        mode |= ENABLE_VIRTUAL_TERMINAL_PROCESSING;
+        SetConsoleMode(handle, mode)
+    }
+}
+
+pub fn active_console_window() -> Result<()> {
+    unsafe {
+        let hwnd = GetConsoleWindow();
+        let result = SetForegroundWindow(hwnd).ok();
+        if let Err(err) = result {
+            if err.code() != S_OK {
+                Err(err)
+            } else {
+                Ok(())
+            }
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub fn notify_message(title: &str, message: &str) -> Result<()> {
+    let template = ToastTemplateType::ToastText01;
+    let toast_xml = ToastNotificationManager::GetTemplateContent(template)?;
+    let text_elements = toast_xml.GetElementsByTagName(&HSTRING::from("text"))?;
+    text_elements
+        .Item(0)?
+        .AppendChild(&toast_xml.CreateTextNode(&HSTRING::from(message))?)?;
+    let toast = ToastNotification::CreateToastNotification(&toast_xml)?;
+    let app_id = HSTRING::from(title);
+    let notifier = ToastNotificationManager::CreateToastNotifierWithId(&app_id)?;
+    notifier.Show(&toast)
+}
+
+// 当前进程是否以管理员身份运行
+pub fn is_elevated() -> bool {
+    unsafe { IsUserAnAdmin().as_bool() }
+}
+
+// 以管理员身份启动一个程序，会弹出用户账户控制的确认窗口，用户拒绝时返回错误
+pub fn run_elevated(exe: &Path) -> Result<()> {
+    let exe = HSTRING::from(exe);
+    let instance = unsafe {
+        ShellExecuteW(
+            None,
+            &HSTRING::from("runas"),
+            &exe,
+            PCWSTR::null(),
+            PCWSTR::null(),
+            SW_SHOWNORMAL,
+        )
+    };
+    // 返回值不大于 32 时表示失败
+    if instance.0 as usize <= 32 {
+        return Err(Error::from_thread());
+    }
+    Ok(())
+}
+
+// `GetVersionEx` 会受兼容性清单影响而返回错误的版本，因此直接读取注册表
+pub fn system_info() -> SystemInfo {
+    let current_version =
+        RegKey::predef(HKEY_LOCAL_MACHINE).open_subkey(r"SOFTWARE\Microsoft\Windows NT\CurrentVersion");
+    let number = |name: &str| {
+        let key = current_version.as_ref().ok()?;
+        key.get_value::<u32, _>(name).ok()
+    };
+    let build = current_version
+        .as_ref()
+        .ok()
+        .and_then(|key| key.get_value::<String, _>("CurrentBuildNumber").ok())
+        .and_then(|build| build.parse().ok())
+        .unwrap_or_default();
+
+    // 用户在“图形设置”中的选择，形如 `SwapEffectUpgradeEnable=1;VRROptimizeEnable=0;`
+    let gpu_settings: String = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(r"Software\Microsoft\DirectX\UserGpuPreferences")
+        .and_then(|key| key.get_value("DirectXUserGlobalSettings"))
+        .unwrap_or_default();
+    let upgrade_enabled = gpu_settings
+        .split(';')
+        .any(|item| item.trim() == "SwapEffectUpgradeEnable=1");
+
+    SystemInfo {
+        major: number("CurrentMajorVersionNumber").unwrap_or(10),
+        minor: number("CurrentMinorVersionNumber").unwrap_or_default(),
+        build,
+        revision: number("UBR").unwrap_or_default(),
+        console_hwnd: unsafe { GetConsoleWindow() }.0 as usize,
+        swap_effect_upgrade: upgrade_enabled && build >= SWAP_EFFECT_UPGRADE_MIN_BUILD,
+    }
+}
+
+pub fn list_displays() -> Result<Vec<DisplayInfo>> {
+    let mut displays = Vec::new();
+    unsafe {
+        EnumDisplayMonitors(
+            None,
+            None,
+            Some(enum_displays_callback),
+            LPARAM(&mut displays as *mut _ as _),
+        )
+        .ok()?;
+    }
+    Ok(displays)
+}
+
+// 调用者可用的剩余空间，受磁盘配额限制
+pub fn free_disk_space(path: &Path) -> std::io::Result<u64> {
+    let path = HSTRING::from(path);
+    let mut available = 0;
+    unsafe { GetDiskFreeSpaceExW(PCWSTR(path.as_ptr()), Some(&mut available as *mut u64), None, None)? };
+    Ok(available)
+}
+
+// 系统的注册表
+pub fn registry() -> Arc<dyn RegistryStore> {
+    Arc::new(SystemRegistry)
+}
+
+struct SystemRegistry;
+
+impl SystemRegistry {
+    fn root(hive: Hive) -> RegKey {
+        RegKey::predef(match hive {
+            Hive::CurrentUser => HKEY_CURRENT_USER,
+            Hive::LocalMachine => HKEY_LOCAL_MACHINE,
+        })
+    }
+}
+
+// 不存在的键或值视为 `None`
+fn not_found_as_none<T>(result: std::io::Result<T>) -> std::io::Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+impl RegistryStore for SystemRegistry {
+    fn get_string(&self, hive: Hive, path: &str, name: &str) -> std::io::Result<Option<String>> {
+        let Some(key) = not_found_as_none(SystemRegistry::root(hive).open_subkey(path))? else {
+            return Ok(None);
+        };
+        not_found_as_none(key.get_value(name))
+    }
+
+    fn key_exists(&self, hive: Hive, path: &str) -> std::io::Result<bool> {
+        Ok(not_found_as_none(SystemRegistry::root(hive).open_subkey(path))?.is_some())
+    }
+
+    fn subkeys(&self, hive: Hive, path: &str) -> std::io::Result<Vec<String>> {
+        match not_found_as_none(SystemRegistry::root(hive).open_subkey(path))? {
+            Some(key) => key.enum_keys().collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn values(&self, hive: Hive, path: &str) -> std::io::Result<Vec<(String, String)>> {
+        let Some(key) = not_found_as_none(SystemRegistry::root(hive).open_subkey(path))? else {
+            return Ok(Vec::new());
+        };
+        let mut values = Vec::new();
+        for item in key.enum_values() {
+            let (name, value) = item?;
+            if let Ok(value) = String::from_reg_value(&value) {
+                values.push((name, value));
+            }
+        }
+        Ok(values)
+    }
+
+    fn create_key(&self, hive: Hive, path: &str) -> std::io::Result<()> {
+        SystemRegistry::root(hive).create_subkey(path).map(|_| ())
+    }
+
+    fn set_string(&self, hive: Hive, path: &str, name: &str, value: &str) -> std::io::Result<()> {
+        let (key, _) = SystemRegistry::root(hive).create_subkey(path)?;
+        key.set_value(name, &value)
+    }
+
+    fn delete_tree(&self, hive: Hive, path: &str) -> std::io::Result<()> {
+        not_found_as_none(SystemRegistry::root(hive).delete_subkey_all(path)).map(|_| ())
+    }
+}
+
+// “设置 - 网络 - 代理”中手动设置的代理服务器，reqwest 同样会使用它
+pub fn system_proxy() -> Option<String> {
+    let settings = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(r"Software\Microsoft\Windows\CurrentVersion\Internet Settings")
+        .ok()?;
+    let enabled: u32 = settings.get_value("ProxyEnable").ok()?;
+    (enabled != 0).then(|| settings.get_value("ProxyServer").ok()).flatten()
+}
+
+// 回调函数：被 `EnumDisplayMonitors` 调用
+unsafe extern "system" fn enum_displays_callback(monitor: HMONITOR, _: HDC, _: *mut RECT, lparam: LPARAM) -> BOOL {
+    let displays = &mut *(lparam.0 as *mut Vec<DisplayInfo>);
+
+    let mut info = MONITORINFOEXW::default();
+    info.monitorInfo.cbSize = size_of::<MONITORINFOEXW>() as u32;
+    if !GetMonitorInfoW(monitor, &mut info as *mut _ as *mut MONITORINFO).as_bool() {
+        return true.into();
+    }
+    // 获取失败时按 100% 缩放处理
+    let (mut dpi, mut dpi_y) = (96, 96);
+    if GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi, &mut dpi_y).is_err() {
+        dpi = 96;
+    }
+
+    let rect = info.monitorInfo.rcMonitor;
+    let work = info.monitorInfo.rcWork;
+    displays.push(DisplayInfo {
+        name: device_name(&info),
+        primary: info.monitorInfo.dwFlags & MONITORINFOF_PRIMARY != 0,
+        x: rect.left,
+        y: rect.top,
+        width: rect.right - rect.left,
+        height: rect.bottom - rect.top,
+        work_area: Rect {
+            x: work.left,
+            y: work.top,
+            width: work.right - work.left,
+            height: work.bottom - work.top,
+        },
+        dpi,
+        scale: dpi as f64 / 96.0,
+    });
+
+    true.into()
+}
+
+// 回调函数：被 `EnumWindows` 调用
+unsafe extern "system" fn enum_windows_callback(hwnd: HWND, lparam: LPARAM) -> BOOL {
+    let windows = &mut *(lparam.0 as *mut Vec<WindowInfo>);
+
+    let get_f_into_string = |f: unsafe fn(HWND, &mut [u16]) -> i32, hwnd: HWND| {
+        let mut buffer = vec![0u16; 1024_usize];
+        if f(hwnd, &mut buffer) > 0 {
+            let s = String::from_utf16(&buffer)?;
+            Ok(String::from(s.trim_end_matches('\0')))
+        } else {
+            Err(Error::from_thread())
+        }
+    };
+
+    let get_window_size = |hwnd: HWND| {
+        let mut rect = std::mem::zeroed();
+        if GetWindowRect(hwnd, &mut rect).is_ok() {
+            Some(rect)
+        } else {
+            None
+        }
+    };
+
+    let title = get_f_into_string(GetWindowTextW, hwnd).unwrap_or("??????".to_string());
+
+    let class_name = get_f_into_string(GetClassNameW, hwnd).unwrap_or("??????".to_string());
+
+    let rect = get_window_size(hwnd).unwrap_or_default();
+    let (x, y, width, height) = (rect.left, rect.top, rect.right - rect.left, rect.bottom - rect.top);
+
+    if !title.is_empty() && width > 10 && height > 10 {
+        let (pid, process) = window_process(hwnd);
+        // 程序在清单中声明了按显示器感知 DPI，窗口的坐标与尺寸均为物理像素
+        let dpi = match GetDpiForWindow(hwnd) {
+            0 => 96,
+            dpi => dpi,
+        };
+        let scale = dpi as f64 / 96.0;
+        let client = client_rect(hwnd);
+        windows.push(WindowInfo {
+            class_name,
+            title: title.to_string(),
+            hwnd: hwnd.0 as usize,
+            height,
+            width,
+            x,
+            y,
+            pid,
+            process,
+            visible: IsWindowVisible(hwnd).as_bool(),
+            minimized: IsIconic(hwnd).as_bool(),
+            maximized: IsZoomed(hwnd).as_bool(),
+            monitor: window_monitor(hwnd),
+            client,
+            dpi,
+            scale,
+            logical_client: Size::logical(client.width, client.height, scale),
+        });
+    }
+
+    true.into() // 返回 true 继续枚举
+}
+
+// 窗口所属进程的 id 与可执行文件名。以管理员权限运行的游戏通常仍可查询，受保护的进程则不行
+unsafe fn window_process(hwnd: HWND) -> (u32, String) {
+    let mut pid = 0;
+    GetWindowThreadProcessId(hwnd, Some(&mut pid as *mut u32));
+    let Ok(process) = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) else {
+        return (pid, String::new());
+    };
+    let mut buffer = [0u16; 1024];
+    let mut size = buffer.len() as u32;
+    let path = QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(buffer.as_mut_ptr()), &mut size)
+        .map(|_| String::from_utf16_lossy(&buffer[..size as usize]));
+    let _ = CloseHandle(process);
+    let name = path.ok().and_then(|path| path.rsplit('\\').next().map(str::to_owned));
+    (pid, name.unwrap_or_default())
+}
+
+unsafe fn window_monitor(hwnd: HWND) -> String {
+    let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
+    let mut info = MONITORINFOEXW::default();
+    info.monitorInfo.cbSize = size_of::<MONITORINFOEXW>() as u32;
+    if GetMonitorInfoW(monitor, &mut info as *mut _ as *mut MONITORINFO).as_bool() {
+        device_name(&info)
+    } else {
+        String::new()
+    }
+}
+
+// 客户区的大小来自 `GetClientRect`，左上角再换算为屏幕坐标
+unsafe fn client_rect(hwnd: HWND) -> Rect {
+    let mut rect = RECT::default();
+    if GetClientRect(hwnd, &mut rect).is_err() {
+        return Rect::default();
+    }
+    let mut origin = POINT::default();
+    let _ = ClientToScreen(hwnd, &mut origin);
+    Rect {
+        x: origin.x,
+        y: origin.y,
+        width: rect.right - rect.left,
+        height: rect.bottom - rect.top,
+    }
+}
+
+// 显示器的设备名，如 `\\.\DISPLAY1`
+fn device_name(info: &MONITORINFOEXW) -> String {
+    String::from_utf16_lossy(&info.szDevice)
+        .trim_end_matches('\0')
+        .to_string()
+}
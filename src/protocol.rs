@@ -0,0 +1,532 @@
+// WebSocket 消息格式。每条消息为 `{ "action": ..., "data": ..., "id": ... }`，
+// 客户端可先发送 `hello` 协商协议版本与功能；未握手的连接按版本 1（莫娜占卜铺使用的格式）处理。
+
+use crate::{
+    ansi::{self, Span},
+    game::GameWindow,
+    hub::Role,
+    platform::Rect,
+    scan::{OutputLine, ScanInfo, Stream},
+    server::YasUpdateState,
+    yas_args::ArgError,
+};
+
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+pub const PROTOCOL_VERSIONS: [u32; 2] = [1, 2];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    // 按扫描 id 重新连接并补发输出
+    Attach,
+    // `yas-output` 附带解析后的 ANSI 颜色
+    StyledOutput,
+    // 以控制者身份订阅扫描并取消扫描
+    Control,
+    // 接收游戏窗口的出现、关闭与分辨率变化，需要令牌具有窗口权限
+    WindowEvents,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 4] = [
+        Capability::Attach,
+        Capability::StyledOutput,
+        Capability::Control,
+        Capability::WindowEvents,
+    ];
+    // 未握手的连接不会收到原版没有的推送
+    pub const LEGACY: [Capability; 3] = [Capability::Attach, Capability::StyledOutput, Capability::Control];
+}
+
+// 一个连接协商后的状态
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub version: u32,
+    pub capabilities: Vec<Capability>,
+}
+
+impl Session {
+    // 未握手的旧客户端保持原有行为
+    pub fn legacy() -> Session {
+        Session {
+            version: 1,
+            capabilities: Capability::LEGACY.to_vec(),
+        }
+    }
+
+    // 最新版本且启用全部功能，用于 SSE 等不经过握手的场景
+    pub fn latest() -> Session {
+        Session {
+            version: PROTOCOL_VERSIONS[PROTOCOL_VERSIONS.len() - 1],
+            capabilities: Capability::ALL.to_vec(),
+        }
+    }
+
+    pub fn has(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ClientMessage {
+    // 请求 id，会原样出现在对应的响应中
+    #[serde(default)]
+    pub id: Value,
+    #[serde(flatten)]
+    pub action: ClientAction,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "action", content = "data", rename_all = "kebab-case")]
+pub enum ClientAction {
+    Hello(HelloRequest),
+    Api(ApiRequest),
+    Attach(AttachRequest),
+    Cancel(CancelRequest),
+}
+
+impl ClientAction {
+    pub const NAMES: [&'static str; 4] = ["hello", "api", "attach", "cancel"];
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct HelloRequest {
+    // 客户端支持的协议版本
+    pub versions: Vec<u32>,
+    // 客户端希望启用的功能，无法识别的功能会被忽略
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ApiRequest {
+    pub url: String,
+    pub method: String,
+    pub body: Option<Value>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AttachRequest {
+    pub id: Uuid,
+    // 从哪一行开始补发输出
+    #[serde(default)]
+    pub since: u64,
+    // 以观看者还是控制者的身份订阅，需要协商 `control` 才能成为控制者
+    #[serde(default)]
+    pub role: Role,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CancelRequest {
+    pub id: Uuid,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum ServerMessage {
+    Hello {
+        data: HelloResponse,
+        id: Value,
+    },
+    Api {
+        data: ApiResponse,
+        id: Value,
+    },
+    Attach {
+        data: AttachResponse,
+        id: Value,
+    },
+    Cancel {
+        data: CancelResponse,
+        id: Value,
+    },
+    // yas 输出的一行，服务端提示没有 `seq` 等字段
+    YasOutput {
+        data: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        stream: Option<Stream>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        time: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        styled: Option<Box<StyledText>>,
+    },
+    Yas {
+        data: YasEvent,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        scan: Option<Uuid>,
+    },
+    Error {
+        data: ErrorBody,
+        id: Value,
+    },
+    // 客户端接收过慢，部分 yas 输出未发送（版本 2）
+    OutputDropped {
+        data: OutputDropped,
+    },
+    // 游戏窗口出现、关闭与分辨率改变，需要协商 `window-events`，也会通过 `/api/events` 发送
+    WindowAppeared {
+        data: Box<GameWindow>,
+    },
+    WindowClosed {
+        data: Box<GameWindow>,
+    },
+    WindowResized {
+        data: Box<WindowResized>,
+    },
+    // 以下仅通过 `/api/events` 发送
+    // 扫描开始或结束
+    Scan {
+        data: Box<ScanInfo>,
+    },
+    YasUpdate {
+        data: YasUpdate,
+    },
+    Window {
+        data: Box<WindowEvent>,
+    },
+    // 部分事件过旧或接收过慢而未能发送
+    EventsMissed {
+        data: EventsMissed,
+    },
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HelloResponse {
+    pub version: u32,
+    pub capabilities: Vec<Capability>,
+    pub server: String,
+    pub server_version: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ApiResponse {
+    pub status: u16,
+    pub body: Value,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AttachResponse {
+    // 404 为扫描不存在，409 为已有其他客户端在控制该扫描
+    pub status: u16,
+    // 实际获得的身份，扫描已结束时只能为观看者
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CancelResponse {
+    // 403 为不是该扫描的控制者，404 为扫描不在运行
+    pub status: u16,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum YasEvent {
+    // yas 已启动
+    Load,
+    // 重新连接的扫描已结束
+    Exit,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct StyledText {
+    pub spans: Vec<Span>,
+    pub html: String,
+    pub plain: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OutputDropped {
+    // 自上次通知以来丢弃的行数
+    pub dropped: u64,
+    // 本次连接丢弃的总行数
+    pub total: u64,
+}
+
+// yas 的更新进度，与 `GET /api/upgrade/yas` 的 `msg` 一致
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct YasUpdate {
+    pub state: YasUpdateState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downloaded: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum WindowEvent {
+    // 网页端请求将该窗口切换到前台
+    Activated { hwnd: usize },
+}
+
+// 游戏画面的分辨率改变，如切换全屏或窗口大小，`previous` 为之前的客户区
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct WindowResized {
+    pub window: GameWindow,
+    pub previous: Rect,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EventsMissed {
+    pub missed: u64,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    // 无法解析的 JSON 或字段不符合格式
+    BadRequest,
+    // 未知的 action
+    NotSupported,
+    // 没有双方都支持的协议版本
+    UnsupportedVersion,
+}
+
+impl ServerMessage {
+    pub fn api(id: Value, status: u16, body: Value) -> ServerMessage {
+        ServerMessage::Api {
+            data: ApiResponse { status, body },
+            id,
+        }
+    }
+
+    pub fn error(id: Value, code: ErrorCode, message: impl Into<String>) -> ServerMessage {
+        ServerMessage::Error {
+            data: ErrorBody {
+                code,
+                message: message.into(),
+                details: None,
+            },
+            id,
+        }
+    }
+
+    // 已有扫描在运行时，以 api 响应的形式拒绝新的扫描请求
+    pub fn busy(id: Value, scan: &ScanInfo) -> ServerMessage {
+        ServerMessage::api(id, 409, serde_json::json!({"msg": "busy", "scan": scan}))
+    }
+
+    // yas 参数未通过校验，逐条返回被拒绝的参数及原因
+    pub fn invalid_argv(id: Value, errors: &[ArgError]) -> ServerMessage {
+        ServerMessage::api(id, 400, serde_json::json!({"msg": "invalid-argv", "errors": errors}))
+    }
+
+    // 服务端自己的提示，和 yas 输出一样显示在网页的终端里
+    pub fn note(text: impl Into<String>) -> ServerMessage {
+        ServerMessage::YasOutput {
+            data: text.into(),
+            seq: None,
+            stream: None,
+            time: None,
+            styled: None,
+        }
+    }
+
+    pub fn output(line: OutputLine, session: &Session) -> ServerMessage {
+        // 带颜色的输出额外附上解析后的片段，`data` 仍为原始文本
+        let styled = (session.has(Capability::StyledOutput) && ansi::contains_escape(&line.text)).then(|| {
+            let spans = ansi::parse(&line.text);
+            Box::new(StyledText {
+                html: ansi::to_html(&spans),
+                plain: ansi::to_plain(&spans),
+                spans,
+            })
+        });
+        ServerMessage::YasOutput {
+            data: line.text,
+            seq: Some(line.seq),
+            stream: Some(line.stream),
+            time: Some(line.time),
+            styled,
+        }
+    }
+
+    // 版本 1 的客户端只认识 `yas-output`，以提示的形式显示
+    pub fn output_dropped(dropped: u64, total: u64, session: &Session) -> ServerMessage {
+        if session.version >= 2 {
+            ServerMessage::OutputDropped {
+                data: OutputDropped { dropped, total },
+            }
+        } else {
+            ServerMessage::note(format!("⚠️网络过慢，跳过了 {dropped} 行输出"))
+        }
+    }
+
+    pub fn yas(event: YasEvent, scan: Option<Uuid>) -> ServerMessage {
+        ServerMessage::Yas { data: event, scan }
+    }
+}
+
+// 协商版本与功能，版本取双方都支持的最高版本。返回 `hello` 的回复，没有双方都支持的版本时返回错误帧
+pub fn negotiate(hello: &HelloRequest, id: Value) -> Result<(Session, ServerMessage), ServerMessage> {
+    let Some(version) = hello
+        .versions
+        .iter()
+        .copied()
+        .filter(|v| PROTOCOL_VERSIONS.contains(v))
+        .max()
+    else {
+        let message = format!("supported protocol versions are {PROTOCOL_VERSIONS:?}");
+        return Err(ServerMessage::error(id, ErrorCode::UnsupportedVersion, message));
+    };
+    let capabilities: Vec<Capability> = Capability::ALL
+        .into_iter()
+        .filter(|c| {
+            let name = serde_json::to_value(c).unwrap();
+            hello.capabilities.iter().any(|requested| name == requested.as_str())
+        })
+        .collect();
+    let data = HelloResponse {
+        version,
+        capabilities: capabilities.clone(),
+        server: "frostflake-rs".to_owned(),
+        server_version: env!("CARGO_PKG_VERSION").to_owned(),
+    };
+    Ok((Session { version, capabilities }, ServerMessage::Hello { data, id }))
+}
+
+// 解析客户端消息，失败时返回应当回复的错误帧
+pub fn parse_client_message(text: &str) -> Result<ClientMessage, ServerMessage> {
+    let raw: Value = serde_json::from_str(text)
+        .map_err(|err| ServerMessage::error(Value::Null, ErrorCode::BadRequest, err.to_string()))?;
+    let id = raw.get("id").cloned().unwrap_or_default();
+    match raw.get("action").and_then(Value::as_str) {
+        Some(action) if ClientAction::NAMES.contains(&action) => {
+            serde_json::from_value(raw).map_err(|err| ServerMessage::error(id, ErrorCode::BadRequest, err.to_string()))
+        },
+        Some(action) => Err(ServerMessage::error(
+            id,
+            ErrorCode::NotSupported,
+            format!("action `{action}` is not supported"),
+        )),
+        None => Err(ServerMessage::error(id, ErrorCode::BadRequest, "missing `action`")),
+    }
+}
+
+// 客户端与服务端消息的 JSON Schema，供网页端生成类型定义
+pub fn schema() -> Value {
+    serde_json::json!({
+        "client": schema_for!(ClientMessage),
+        "server": schema_for!(ServerMessage),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn hello(versions: &[u32], capabilities: &[&str]) -> HelloRequest {
+        HelloRequest {
+            versions: versions.to_vec(),
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    // schema 中各个消息的 `action`
+    fn actions(schema: &Value) -> Vec<&str> {
+        schema["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|variant| variant["properties"]["action"]["const"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn negotiates_the_highest_common_version() {
+        let (session, response) = negotiate(&hello(&[1, 2, 99], &["attach", "bogus"]), json!(7)).unwrap();
+        assert_eq!(session.version, 2);
+        assert_eq!(session.capabilities, [Capability::Attach]);
+        let response = serde_json::to_value(response).unwrap();
+        assert_eq!(response["action"], "hello");
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["data"]["version"], 2);
+        assert_eq!(response["data"]["capabilities"], json!(["attach"]));
+    }
+
+    #[test]
+    fn version_mismatch_echoes_the_id() {
+        let Err(error) = negotiate(&hello(&[3, 4], &[]), json!("handshake")) else {
+            panic!("version 3 and 4 are not supported");
+        };
+        let error = serde_json::to_value(error).unwrap();
+        assert_eq!(error["action"], "error");
+        assert_eq!(error["id"], "handshake");
+        assert_eq!(error["data"]["code"], "unsupported-version");
+    }
+
+    #[test]
+    fn rejects_unknown_actions() {
+        let Err(error) = parse_client_message(r#"{"action": "shutdown", "id": 3}"#) else {
+            panic!("`shutdown` is not an action");
+        };
+        let error = serde_json::to_value(error).unwrap();
+        assert_eq!(
+            (&error["id"], &error["data"]["code"]),
+            (&json!(3), &json!("not-supported"))
+        );
+        let Err(error) = parse_client_message(r#"{"data": {}, "id": 4}"#) else {
+            panic!("`action` is required");
+        };
+        assert_eq!(serde_json::to_value(error).unwrap()["data"]["code"], "bad-request");
+        let Err(error) = parse_client_message("not json") else {
+            panic!("the message is not JSON");
+        };
+        assert_eq!(serde_json::to_value(error).unwrap()["id"], Value::Null);
+    }
+
+    #[test]
+    fn parses_known_actions() {
+        let message = parse_client_message(r#"{"action": "hello", "data": {"versions": [2]}, "id": 1}"#).unwrap();
+        assert!(matches!(message.action, ClientAction::Hello(ref hello) if hello.versions == [2]));
+        assert_eq!(message.id, 1);
+        // 已知的 action 缺少字段时为格式错误而不是不支持
+        let Err(error) = parse_client_message(r#"{"action": "attach", "data": {}, "id": 2}"#) else {
+            panic!("`attach` needs an id");
+        };
+        assert_eq!(serde_json::to_value(error).unwrap()["data"]["code"], "bad-request");
+    }
+
+    #[test]
+    fn schema_lists_every_message() {
+        let schema = schema();
+        assert_eq!(actions(&schema["client"]), ClientAction::NAMES);
+        assert_eq!(
+            actions(&schema["server"]),
+            [
+                "hello",
+                "api",
+                "attach",
+                "cancel",
+                "yas-output",
+                "yas",
+                "error",
+                "output-dropped",
+                "window-appeared",
+                "window-closed",
+                "window-resized",
+                "scan",
+                "yas-update",
+                "window",
+                "events-missed",
+            ]
+        );
+    }
+}
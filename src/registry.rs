@@ -1,48 +1,638 @@
-use std::error::Error;
+// 自定义 URI 协议的注册。注册表通过 `RegistryStore` 读写，测试中可以使用保存在内存中的实现。
+//
+// 覆盖其他程序（如原版霜华）的注册之前，先把原来的键复制到同一位置的 `Software\frostflake-rs\Schemes\<scheme>\Backup`，
+// 同一键下的 `Command` 记录本程序写入的命令，据此判断协议是否被其他程序接管，卸载时再把原来的注册放回去。
+//
+// `HKEY_CLASSES_ROOT` 是 `HKCU\Software\Classes` 与 `HKLM\Software\Classes` 合并后的视图，同名的键以当前用户的为准。
+// 写入 `HKLM` 需要管理员权限，因此可以只为当前用户注册；两处都有注册时，所有用户的注册被当前用户的遮蔽。
 
-use winreg::RegKey;
+use crate::platform::{Hive, RegistryStore};
 
-pub fn get_registration_path(scheme: &str) -> Result<String, Box<dyn Error>> {
-    let hkey_root = RegKey::predef(winreg::enums::HKEY_CLASSES_ROOT);
-    let key = hkey_root.open_subkey(String::from(scheme))?;
-    let subkey = key.open_subkey("shell\\open\\command")?;
-    let value: String = subkey.get_value("")?;
-    let path = value.split('"').nth(1).ok_or("Failed to parse path")?;
-    Ok(path.to_string())
-}
+use serde::Serialize;
+use std::{env, fmt, io, path::PathBuf};
 
 // https://learn.microsoft.com/en-us/previous-versions/windows/internet-explorer/ie-developer/platform-apis/aa767914(v=vs.85)
 // HKEY_CLASSES_ROOT
-//    alert
-//       (Default) = "URL:Alert Protocol"
-//       URL Protocol = ""
-//       DefaultIcon
-//          (Default) = "alert.exe,1"
-//       shell
-//          open
-//             command
-//                (Default) = "C:\Program Files\Alert\alert.exe" "%1"
-
-pub fn create_scheme_registration(scheme: &str, path: &str) -> Result<(), Box<dyn Error>> {
-    let hkey_root = RegKey::predef(winreg::enums::HKEY_CLASSES_ROOT);
-    if hkey_root.open_subkey(scheme).is_ok() {
-        hkey_root
-            .delete_subkey_all(scheme)
-            .expect("Failed to delete the old registration");
+//    alert
+//       (Default) = "URL:Alert Protocol"
+//       URL Protocol = ""
+//       DefaultIcon
+//          (Default) = "alert.exe,1"
+//       shell
+//          open
+//             command
+//                (Default) = "C:\Program Files\Alert\alert.exe" "%1"
+
+#[derive(Debug)]
+pub enum RegistrationError {
+    // 没有注册该协议
+    Missing,
+    // 注册的命令无法解析出程序路径
+    Malformed(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for RegistrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistrationError::Missing => write!(f, "the scheme is not registered"),
+            RegistrationError::Malformed(command) => write!(f, "failed to parse the command `{command}`"),
+            RegistrationError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for RegistrationError {}
+
+impl From<io::Error> for RegistrationError {
+    fn from(err: io::Error) -> Self {
+        RegistrationError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegistrationScope {
+    // 当前用户，不需要管理员权限
+    User,
+    // 所有用户
+    Machine,
+}
+
+impl RegistrationScope {
+    pub fn hive(self) -> Hive {
+        match self {
+            RegistrationScope::User => Hive::CurrentUser,
+            RegistrationScope::Machine => Hive::LocalMachine,
+        }
+    }
+
+    // 建议的安装目录，为当前用户安装时不需要管理员权限即可写入。Windows 上总有这两个环境变量
+    pub fn install_dir(self) -> PathBuf {
+        let (var, dir) = match self {
+            RegistrationScope::User => ("LOCALAPPDATA", r"Programs\frostflake-rs"),
+            RegistrationScope::Machine => ("ProgramFiles", "frostflake-rs"),
+        };
+        env::var_os(var)
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir)
+            .join(dir)
     }
+}
+
+// 两处注册的命令
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Registrations {
+    pub user: Option<String>,
+    pub machine: Option<String>,
+}
+
+impl Registrations {
+    // 实际生效的注册
+    pub fn active(&self) -> Option<(RegistrationScope, &str)> {
+        match (&self.user, &self.machine) {
+            (Some(command), _) => Some((RegistrationScope::User, command)),
+            (None, Some(command)) => Some((RegistrationScope::Machine, command)),
+            (None, None) => None,
+        }
+    }
+
+    // 所有用户的注册被当前用户的注册遮蔽
+    pub fn shadowed(&self) -> bool {
+        self.user.is_some() && self.machine.is_some()
+    }
+}
+
+// 协议与当前程序的关系，决定启动时是否需要重新注册
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemeStatus {
+    // 没有注册
+    Missing,
+    // 指向当前程序，且与本程序写入的内容相同
+    Current,
+    // 指向当前程序，但命令的参数不同或缺少 `URL Protocol`，可能被其他程序改动过
+    Modified { command: String },
+    // 指向其他程序
+    Other { path: String },
+    // 命令无法解析
+    Malformed { command: String },
+}
+
+impl SchemeStatus {
+    pub fn needs_update(&self) -> bool {
+        *self != SchemeStatus::Current
+    }
+}
+
+fn scheme_key(scheme: &str) -> String {
+    format!(r"Software\Classes\{scheme}")
+}
+
+fn command_key(scheme: &str) -> String {
+    format!(r"Software\Classes\{scheme}\shell\open\command")
+}
+
+// 本程序写入的命令
+pub fn command_for(path: &str) -> String {
+    format!("\"{path}\" \"%1\"")
+}
+
+// 从命令中取出程序路径。路径可以带引号，也可以不带；不带引号而路径中有空格时，取到第一个 `.exe` 为止，
+// 与系统的解析方式相同
+pub fn parse_command(command: &str) -> Option<String> {
+    let command = command.trim();
+    let path = match command.strip_prefix('"') {
+        Some(rest) => &rest[..rest.find('"')?],
+        None => match command.to_ascii_lowercase().find(".exe") {
+            Some(end) => &command[..end + ".exe".len()],
+            None => command.split_whitespace().next()?,
+        },
+    };
+    let path = path.trim();
+    (!path.is_empty()).then(|| path.to_owned())
+}
+
+pub fn registrations(store: &dyn RegistryStore, scheme: &str) -> Result<Registrations, RegistrationError> {
+    Ok(Registrations {
+        user: store.get_string(Hive::CurrentUser, &command_key(scheme), "")?,
+        machine: store.get_string(Hive::LocalMachine, &command_key(scheme), "")?,
+    })
+}
+
+// 生效的注册指向的程序
+pub fn get_registration_path(store: &dyn RegistryStore, scheme: &str) -> Result<String, RegistrationError> {
+    let registrations = registrations(store, scheme)?;
+    let (_, command) = registrations.active().ok_or(RegistrationError::Missing)?;
+    parse_command(command).ok_or_else(|| RegistrationError::Malformed(command.to_owned()))
+}
+
+// 生效的注册与当前程序的关系
+pub fn scheme_status(store: &dyn RegistryStore, scheme: &str, path: &str) -> Result<SchemeStatus, RegistrationError> {
+    let registrations = registrations(store, scheme)?;
+    let Some((scope, command)) = registrations.active() else {
+        return Ok(SchemeStatus::Missing);
+    };
+    let command = command.to_owned();
+    let Some(registered) = parse_command(&command) else {
+        return Ok(SchemeStatus::Malformed { command });
+    };
+    // Windows 的路径不区分大小写
+    if registered.to_lowercase() != path.to_lowercase() {
+        return Ok(SchemeStatus::Other { path: registered });
+    }
+    let url_protocol = store.get_string(scope.hive(), &scheme_key(scheme), "URL Protocol")?;
+    if command != command_for(&registered) || url_protocol.is_none() {
+        return Ok(SchemeStatus::Modified { command });
+    }
+    Ok(SchemeStatus::Current)
+}
+
+// 删除 `scope` 中原有的注册，再注册为 `path`
+pub fn create_scheme_registration(
+    store: &dyn RegistryStore,
+    scheme: &str,
+    path: &str,
+    scope: RegistrationScope,
+) -> io::Result<()> {
+    let hive = scope.hive();
+    let key = scheme_key(scheme);
+    store.delete_tree(hive, &key)?;
 
     let uri_protocol_str = format!("URL:{scheme} Protocol");
     let icon_path_str = format!("{path},1");
-    let command_str = format!("\"{path}\" \"%1\"");
 
-    let (hkey_scheme, _) = hkey_root.create_subkey(scheme)?;
+    store.set_string(hive, &key, "", &uri_protocol_str)?;
+    store.set_string(hive, &key, "URL Protocol", "")?;
+    store.set_string(hive, &format!(r"{key}\DefaultIcon"), "", &icon_path_str)?;
+    store.set_string(hive, &command_key(scheme), "", &command_for(path))?;
 
-    hkey_scheme.set_value("", &uri_protocol_str)?;
-    hkey_scheme.set_value("URL Protocol", &"")?;
-    let (hkey_icon, _) = hkey_scheme.create_subkey("DefaultIcon")?;
-    let (hkey_open, _) = hkey_scheme.create_subkey("shell\\open\\command")?;
-    hkey_icon.set_value("", &icon_path_str)?;
-    hkey_open.set_value("", &command_str)?;
+    Ok(())
+}
+
+pub fn delete_scheme_registration(store: &dyn RegistryStore, scheme: &str, scope: RegistrationScope) -> io::Result<()> {
+    store.delete_tree(scope.hive(), &scheme_key(scheme))
+}
+
+fn state_key(scheme: &str) -> String {
+    format!(r"Software\frostflake-rs\Schemes\{scheme}")
+}
+
+fn backup_key(scheme: &str) -> String {
+    format!(r"{}\Backup", state_key(scheme))
+}
 
+// 复制键及其所有子键中的字符串值
+fn copy_tree(store: &dyn RegistryStore, hive: Hive, from: &str, to: &str) -> io::Result<()> {
+    store.create_key(hive, to)?;
+    for (name, value) in store.values(hive, from)? {
+        store.set_string(hive, to, &name, &value)?;
+    }
+    for subkey in store.subkeys(hive, from)? {
+        copy_tree(store, hive, &format!(r"{from}\{subkey}"), &format!(r"{to}\{subkey}"))?;
+    }
     Ok(())
 }
+
+// 本程序在 `scope` 中写入的命令
+pub fn our_command(store: &dyn RegistryStore, scheme: &str, scope: RegistrationScope) -> io::Result<Option<String>> {
+    store.get_string(scope.hive(), &state_key(scheme), "Command")
+}
+
+pub fn has_backup(store: &dyn RegistryStore, scheme: &str, scope: RegistrationScope) -> io::Result<bool> {
+    store.key_exists(scope.hive(), &backup_key(scheme))
+}
+
+// 备份 `scope` 中原有的注册，已有备份时不再覆盖。返回是否新做了备份
+fn backup(store: &dyn RegistryStore, scheme: &str, scope: RegistrationScope) -> io::Result<bool> {
+    let hive = scope.hive();
+    if !store.key_exists(hive, &scheme_key(scheme))? || has_backup(store, scheme, scope)? {
+        return Ok(false);
+    }
+    copy_tree(store, hive, &scheme_key(scheme), &backup_key(scheme))?;
+    Ok(true)
+}
+
+// 注册为 `path`，并记录写入的命令。原有的注册不是本程序写入的时先备份；已有的备份不会被覆盖，
+// 因此多次被接管后卸载时恢复的仍是最初的注册。返回是否新做了备份
+pub fn register(store: &dyn RegistryStore, scheme: &str, path: &str, scope: RegistrationScope) -> io::Result<bool> {
+    let command = command_for(path);
+    let existing = store.get_string(scope.hive(), &command_key(scheme), "")?;
+    let ours = our_command(store, scheme, scope)?;
+    let foreign = existing != ours && existing.as_deref() != Some(command.as_str());
+    let backed_up = foreign && backup(store, scheme, scope)?;
+    create_scheme_registration(store, scheme, path, scope)?;
+    store.set_string(scope.hive(), &state_key(scheme), "Command", &command)?;
+    Ok(backed_up)
+}
+
+// 备份并删除 `scope` 中其他程序的注册，用于消除当前用户的注册对本程序为所有用户的注册的遮蔽
+pub fn displace_registration(store: &dyn RegistryStore, scheme: &str, scope: RegistrationScope) -> io::Result<()> {
+    backup(store, scheme, scope)?;
+    delete_scheme_registration(store, scheme, scope)
+}
+
+// 生效的注册被其他程序改写
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Takeover {
+    pub scope: RegistrationScope,
+    pub command: String,
+}
+
+// 本程序注册过、而生效的注册已不是本程序写入的命令时，返回接管者的命令。
+// 其他程序在当前用户下注册、遮蔽了本程序为所有用户的注册，同样视为被接管
+pub fn detect_takeover(store: &dyn RegistryStore, scheme: &str) -> Result<Option<Takeover>, RegistrationError> {
+    let registrations = registrations(store, scheme)?;
+    let Some((scope, command)) = registrations.active() else {
+        return Ok(None);
+    };
+    if our_command(store, scheme, scope)?.as_deref() == Some(command) {
+        return Ok(None);
+    }
+    for ever in [RegistrationScope::User, RegistrationScope::Machine] {
+        if our_command(store, scheme, ever)?.is_some() {
+            return Ok(Some(Takeover {
+                scope,
+                command: command.to_owned(),
+            }));
+        }
+    }
+    Ok(None)
+}
+
+// 放回备份的注册，并忘记本程序的注册。没有备份时什么也不做，返回 `false`
+pub fn restore_registration(store: &dyn RegistryStore, scheme: &str, scope: RegistrationScope) -> io::Result<bool> {
+    let hive = scope.hive();
+    if !has_backup(store, scheme, scope)? {
+        return Ok(false);
+    }
+    store.delete_tree(hive, &scheme_key(scheme))?;
+    copy_tree(store, hive, &backup_key(scheme), &scheme_key(scheme))?;
+    store.delete_tree(hive, &state_key(scheme))?;
+    Ok(true)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Uninstalled {
+    // 放回了本程序注册之前的注册
+    Restored,
+    // 没有备份，删除了本程序的注册
+    Removed,
+    // 协议已被其他程序接管，保留它的注册
+    KeptOther,
+    // 本程序没有在此注册过
+    NotRegistered,
+}
+
+// 卸载 `scope` 中本程序的注册，有备份时放回原来的注册
+pub fn uninstall_registration(
+    store: &dyn RegistryStore,
+    scheme: &str,
+    scope: RegistrationScope,
+) -> io::Result<Uninstalled> {
+    let hive = scope.hive();
+    let ours = our_command(store, scheme, scope)?;
+    let current = store.get_string(hive, &command_key(scheme), "")?;
+    if ours.is_some() && current != ours {
+        store.delete_tree(hive, &state_key(scheme))?;
+        return Ok(Uninstalled::KeptOther);
+    }
+    // 被删除而遮蔽了本程序的注册也会放回去
+    if restore_registration(store, scheme, scope)? {
+        return Ok(Uninstalled::Restored);
+    }
+    if ours.is_none() {
+        return Ok(Uninstalled::NotRegistered);
+    }
+    delete_scheme_registration(store, scheme, scope)?;
+    store.delete_tree(hive, &state_key(scheme))?;
+    Ok(Uninstalled::Removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::MemoryRegistry;
+
+    const SCHEME: &str = "cocogoat-control";
+    const EXE: &str = r"C:\Program Files\frostflake-rs\frostflake-rs.exe";
+    const USER_EXE: &str = r"C:\Users\paimon\AppData\Local\Programs\frostflake-rs\frostflake-rs.exe";
+
+    fn set_command(store: &MemoryRegistry, scope: RegistrationScope, command: &str) {
+        store
+            .set_string(scope.hive(), &scheme_key(SCHEME), "URL Protocol", "")
+            .unwrap();
+        store
+            .set_string(scope.hive(), &command_key(SCHEME), "", command)
+            .unwrap();
+    }
+
+    #[test]
+    fn parses_commands() {
+        let cases = [
+            (r#""C:\Program Files\frostflake-rs\frostflake-rs.exe" "%1""#, Some(EXE)),
+            (r#""C:\Program Files\frostflake-rs\frostflake-rs.exe" %1"#, Some(EXE)),
+            (r#"C:\Program Files\frostflake-rs\frostflake-rs.exe "%1""#, Some(EXE)),
+            (
+                r#"C:\Program Files\frostflake-rs\FROSTFLAKE-RS.EXE"#,
+                Some(r"C:\Program Files\frostflake-rs\FROSTFLAKE-RS.EXE"),
+            ),
+            (r#"C:\tools\launch %1"#, Some(r"C:\tools\launch")),
+            (r#"  "C:\a.exe"  "%1"  "#, Some(r"C:\a.exe")),
+            (r#""C:\a.exe %1"#, None),
+            (r#""" "%1""#, None),
+            ("", None),
+            ("   ", None),
+        ];
+        for (command, expected) in cases {
+            assert_eq!(parse_command(command).as_deref(), expected, "{command}");
+        }
+    }
+
+    #[test]
+    fn registers_and_reads_back() {
+        let store = MemoryRegistry::default();
+        assert!(matches!(
+            get_registration_path(&store, SCHEME),
+            Err(RegistrationError::Missing)
+        ));
+        assert_eq!(scheme_status(&store, SCHEME, EXE).unwrap(), SchemeStatus::Missing);
+
+        // 旧的注册中多余的键会被删除
+        store
+            .set_string(
+                Hive::LocalMachine,
+                &format!(r"{}\shell\edit\command", scheme_key(SCHEME)),
+                "",
+                "notepad.exe",
+            )
+            .unwrap();
+        create_scheme_registration(&store, SCHEME, EXE, RegistrationScope::Machine).unwrap();
+        assert_eq!(get_registration_path(&store, SCHEME).unwrap(), EXE);
+        assert_eq!(scheme_status(&store, SCHEME, EXE).unwrap(), SchemeStatus::Current);
+        assert!(!store
+            .key_exists(Hive::LocalMachine, &format!(r"{}\shell\edit", scheme_key(SCHEME)))
+            .unwrap());
+        assert_eq!(
+            store
+                .get_string(Hive::LocalMachine, r"Software\Classes\cocogoat-control", "")
+                .unwrap()
+                .as_deref(),
+            Some("URL:cocogoat-control Protocol")
+        );
+        assert_eq!(
+            store
+                .get_string(Hive::LocalMachine, &format!(r"{}\DefaultIcon", scheme_key(SCHEME)), "")
+                .unwrap(),
+            Some(format!("{EXE},1"))
+        );
+        // 路径不区分大小写
+        assert!(!scheme_status(&store, SCHEME, &EXE.to_uppercase())
+            .unwrap()
+            .needs_update());
+    }
+
+    #[test]
+    fn user_registration_shadows_machine_registration() {
+        let store = MemoryRegistry::default();
+        create_scheme_registration(&store, SCHEME, EXE, RegistrationScope::Machine).unwrap();
+        let current = registrations(&store, SCHEME).unwrap();
+        assert_eq!(
+            current.active(),
+            Some((RegistrationScope::Machine, command_for(EXE).as_str()))
+        );
+        assert!(!current.shadowed());
+
+        create_scheme_registration(&store, SCHEME, USER_EXE, RegistrationScope::User).unwrap();
+        let current = registrations(&store, SCHEME).unwrap();
+        assert_eq!(
+            current.active(),
+            Some((RegistrationScope::User, command_for(USER_EXE).as_str()))
+        );
+        assert!(current.shadowed());
+        assert_eq!(get_registration_path(&store, SCHEME).unwrap(), USER_EXE);
+        assert_eq!(
+            scheme_status(&store, SCHEME, EXE).unwrap(),
+            SchemeStatus::Other {
+                path: USER_EXE.to_owned()
+            }
+        );
+        assert_eq!(scheme_status(&store, SCHEME, USER_EXE).unwrap(), SchemeStatus::Current);
+
+        delete_scheme_registration(&store, SCHEME, RegistrationScope::User).unwrap();
+        assert_eq!(scheme_status(&store, SCHEME, EXE).unwrap(), SchemeStatus::Current);
+        assert_eq!(registrations(&store, SCHEME).unwrap().user, None);
+    }
+
+    #[test]
+    fn detects_foreign_and_broken_registrations() {
+        let store = MemoryRegistry::default();
+        let machine = RegistrationScope::Machine;
+
+        // 原版霜华或其他程序
+        set_command(&store, machine, r#""C:\Program Files\frostflake\frostflake.exe" "%1""#);
+        assert_eq!(
+            scheme_status(&store, SCHEME, EXE).unwrap(),
+            SchemeStatus::Other {
+                path: r"C:\Program Files\frostflake\frostflake.exe".to_owned()
+            }
+        );
+
+        // 指向本程序，但附加了其他参数
+        let command = format!(r#""{EXE}" --serve-all "%1""#);
+        set_command(&store, machine, &command);
+        assert_eq!(
+            scheme_status(&store, SCHEME, EXE).unwrap(),
+            SchemeStatus::Modified { command }
+        );
+
+        // 没有引号的本程序路径
+        let command = format!("{EXE} %1");
+        set_command(&store, machine, &command);
+        assert_eq!(get_registration_path(&store, SCHEME).unwrap(), EXE);
+        assert_eq!(
+            scheme_status(&store, SCHEME, EXE).unwrap(),
+            SchemeStatus::Modified { command }
+        );
+
+        // 缺少 `URL Protocol` 时浏览器不会使用该协议
+        delete_scheme_registration(&store, SCHEME, machine).unwrap();
+        store
+            .set_string(Hive::LocalMachine, &command_key(SCHEME), "", &command_for(EXE))
+            .unwrap();
+        assert!(matches!(
+            scheme_status(&store, SCHEME, EXE).unwrap(),
+            SchemeStatus::Modified { .. }
+        ));
+
+        let command = r#""C:\Program Files\frostflake-rs\frostflake-rs.exe %1"#;
+        set_command(&store, machine, command);
+        assert!(matches!(
+            get_registration_path(&store, SCHEME),
+            Err(RegistrationError::Malformed(_))
+        ));
+        assert_eq!(
+            scheme_status(&store, SCHEME, EXE).unwrap(),
+            SchemeStatus::Malformed {
+                command: command.to_owned()
+            }
+        );
+
+        // 重新注册后恢复正常
+        create_scheme_registration(&store, SCHEME, EXE, machine).unwrap();
+        assert_eq!(scheme_status(&store, SCHEME, EXE).unwrap(), SchemeStatus::Current);
+    }
+
+    #[test]
+    fn backs_up_and_restores_the_previous_handler() {
+        let store = MemoryRegistry::default();
+        let machine = RegistrationScope::Machine;
+        let original = r#""C:\Program Files\frostflake\frostflake.exe" "%1""#;
+        set_command(&store, machine, original);
+        store
+            .set_string(Hive::LocalMachine, &scheme_key(SCHEME), "", "URL:frostflake")
+            .unwrap();
+        store
+            .create_key(Hive::LocalMachine, &format!(r"{}\shell\print", scheme_key(SCHEME)))
+            .unwrap();
+
+        // 第一次注册时备份原版霜华的注册，再次注册不会重复备份
+        assert!(register(&store, SCHEME, EXE, machine).unwrap());
+        assert!(!register(&store, SCHEME, EXE, machine).unwrap());
+        assert_eq!(scheme_status(&store, SCHEME, EXE).unwrap(), SchemeStatus::Current);
+        assert_eq!(detect_takeover(&store, SCHEME).unwrap(), None);
+
+        // 其他程序接管后再注册回来，备份的仍是原版霜华
+        let hijacker = r#""C:\evil\evil.exe" "%1""#;
+        set_command(&store, machine, hijacker);
+        assert_eq!(
+            detect_takeover(&store, SCHEME).unwrap(),
+            Some(Takeover {
+                scope: machine,
+                command: hijacker.to_owned()
+            })
+        );
+        assert!(!register(&store, SCHEME, EXE, machine).unwrap());
+        assert_eq!(detect_takeover(&store, SCHEME).unwrap(), None);
+
+        assert_eq!(
+            uninstall_registration(&store, SCHEME, machine).unwrap(),
+            Uninstalled::Restored
+        );
+        assert_eq!(
+            get_registration_path(&store, SCHEME).unwrap(),
+            r"C:\Program Files\frostflake\frostflake.exe"
+        );
+        assert_eq!(
+            store
+                .get_string(Hive::LocalMachine, &scheme_key(SCHEME), "")
+                .unwrap()
+                .as_deref(),
+            Some("URL:frostflake")
+        );
+        // 没有值的键也会放回去
+        assert!(store
+            .key_exists(Hive::LocalMachine, &format!(r"{}\shell\print", scheme_key(SCHEME)))
+            .unwrap());
+        assert!(!store.key_exists(Hive::LocalMachine, &state_key(SCHEME)).unwrap());
+        assert_eq!(detect_takeover(&store, SCHEME).unwrap(), None);
+        assert_eq!(
+            uninstall_registration(&store, SCHEME, machine).unwrap(),
+            Uninstalled::NotRegistered
+        );
+    }
+
+    #[test]
+    fn uninstall_without_backup_or_after_takeover() {
+        let store = MemoryRegistry::default();
+        let user = RegistrationScope::User;
+        let machine = RegistrationScope::Machine;
+
+        // 没有原来的注册时直接删除
+        assert!(!register(&store, SCHEME, USER_EXE, user).unwrap());
+        assert!(!restore_registration(&store, SCHEME, user).unwrap());
+        assert_eq!(
+            uninstall_registration(&store, SCHEME, user).unwrap(),
+            Uninstalled::Removed
+        );
+        assert_eq!(registrations(&store, SCHEME).unwrap(), Registrations::default());
+
+        // 被接管后卸载不会删除接管者的注册
+        register(&store, SCHEME, EXE, machine).unwrap();
+        let hijacker = r#""C:\evil\evil.exe" "%1""#;
+        set_command(&store, machine, hijacker);
+        assert_eq!(
+            uninstall_registration(&store, SCHEME, machine).unwrap(),
+            Uninstalled::KeptOther
+        );
+        assert_eq!(
+            registrations(&store, SCHEME).unwrap().machine.as_deref(),
+            Some(hijacker)
+        );
+    }
+
+    #[test]
+    fn user_registration_by_another_program_is_a_takeover() {
+        let store = MemoryRegistry::default();
+        let user = RegistrationScope::User;
+        register(&store, SCHEME, EXE, RegistrationScope::Machine).unwrap();
+
+        let other = r#""C:\Users\paimon\other.exe" "%1""#;
+        set_command(&store, user, other);
+        assert_eq!(
+            detect_takeover(&store, SCHEME).unwrap(),
+            Some(Takeover {
+                scope: user,
+                command: other.to_owned()
+            })
+        );
+
+        // 删除遮蔽的注册之前先备份，卸载时放回
+        displace_registration(&store, SCHEME, user).unwrap();
+        assert_eq!(scheme_status(&store, SCHEME, EXE).unwrap(), SchemeStatus::Current);
+        assert_eq!(
+            uninstall_registration(&store, SCHEME, user).unwrap(),
+            Uninstalled::Restored
+        );
+        assert_eq!(registrations(&store, SCHEME).unwrap().user.as_deref(), Some(other));
+    }
+}
@@ -0,0 +1,157 @@
+// 扫描前的分辨率与缩放检查。yas 按固定的画面布局识别圣遗物，游戏分辨率的宽高比不受支持、
+// 客户区过小或显示器开启了缩放时，扫描容易出错，此时在窗口信息中给出警告。
+
+use crate::{
+    platform::{Rect, WindowInfo},
+    utils::current_dir_file,
+};
+
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs};
+
+// 宽高比的容差，1366×768 等分辨率并不严格等于 16:9
+const ASPECT_TOLERANCE: f64 = 0.01;
+
+// 某个 yas 版本支持的画面
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ResolutionPolicy {
+    // 形如 `16:9`
+    pub aspect_ratios: Vec<String>,
+    // 客户区的最小物理尺寸
+    pub min_width: i32,
+    pub min_height: i32,
+    // 允许的显示器缩放比例，1.0 即 100%
+    pub scales: Vec<f64>,
+}
+
+impl Default for ResolutionPolicy {
+    fn default() -> Self {
+        ResolutionPolicy {
+            aspect_ratios: vec!["16:9".to_owned()],
+            min_width: 1280,
+            min_height: 720,
+            scales: vec![1.0],
+        }
+    }
+}
+
+// 检查结果，`warnings` 为 `aspect-ratio`、`resolution`、`scaling` 中的若干项
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScanCheck {
+    pub warning: bool,
+    pub warnings: Vec<&'static str>,
+    // 匹配到的宽高比，不受支持时为空
+    pub aspect_ratio: Option<String>,
+}
+
+impl ResolutionPolicy {
+    // 从 `yas_resolutions.json` 读取与 yas 版本对应的规则，格式为 `{ "<version>" | "*": policy }`。
+    // 文件不存在或没有匹配的版本时使用内置规则。
+    pub fn load(yas_version: &str) -> ResolutionPolicy {
+        let path = current_dir_file("yas_resolutions.json");
+        let Ok(file) = fs::File::open(&path) else {
+            return ResolutionPolicy::default();
+        };
+        match serde_json::from_reader::<_, HashMap<String, ResolutionPolicy>>(file) {
+            Ok(mut profiles) => profiles
+                .remove(yas_version)
+                .or_else(|| profiles.remove("*"))
+                .unwrap_or_default(),
+            Err(err) => {
+                eprintln!("无法解析 {}：{err}，使用内置分辨率规则", path.display());
+                ResolutionPolicy::default()
+            },
+        }
+    }
+
+    pub fn check(&self, client: Rect, scale: f64) -> ScanCheck {
+        let mut warnings = Vec::new();
+        let aspect_ratio = self
+            .aspect_ratios
+            .iter()
+            .find(|ratio| matches_aspect(ratio, client))
+            .cloned();
+        if aspect_ratio.is_none() {
+            warnings.push("aspect-ratio");
+        }
+        if client.width < self.min_width || client.height < self.min_height {
+            warnings.push("resolution");
+        }
+        if !self.scales.iter().any(|allowed| (allowed - scale).abs() < 1e-3) {
+            warnings.push("scaling");
+        }
+        ScanCheck {
+            warning: !warnings.is_empty(),
+            warnings,
+            aspect_ratio,
+        }
+    }
+
+    pub fn check_window(&self, window: &WindowInfo) -> ScanCheck {
+        self.check(window.client, window.scale)
+    }
+}
+
+fn matches_aspect(ratio: &str, client: Rect) -> bool {
+    let Some((width, height)) = ratio.split_once(':') else {
+        return false;
+    };
+    let (Ok(width), Ok(height)) = (width.trim().parse::<f64>(), height.trim().parse::<f64>()) else {
+        return false;
+    };
+    if client.width <= 0 || client.height <= 0 || height <= 0.0 {
+        return false;
+    }
+    let expected = width / height;
+    let actual = client.width as f64 / client.height as f64;
+    (actual - expected).abs() / expected <= ASPECT_TOLERANCE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(width: i32, height: i32) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn accepts_supported_resolutions() {
+        let policy = ResolutionPolicy::default();
+        for (width, height) in [(1920, 1080), (2560, 1440), (1366, 768), (1280, 720)] {
+            let check = policy.check(client(width, height), 1.0);
+            assert!(!check.warning, "{width}×{height}: {:?}", check.warnings);
+            assert_eq!(check.aspect_ratio.as_deref(), Some("16:9"));
+        }
+    }
+
+    #[test]
+    fn warns_about_unsupported_setups() {
+        let policy = ResolutionPolicy::default();
+        let check = policy.check(client(1920, 1200), 1.0);
+        assert_eq!(check.warnings, vec!["aspect-ratio"]);
+        assert_eq!(check.aspect_ratio, None);
+        assert_eq!(policy.check(client(1024, 576), 1.0).warnings, vec!["resolution"]);
+        assert_eq!(policy.check(client(1920, 1080), 1.5).warnings, vec!["scaling"]);
+        // 最小化的窗口没有客户区
+        assert_eq!(
+            policy.check(client(0, 0), 1.0).warnings,
+            vec!["aspect-ratio", "resolution"]
+        );
+
+        let policy = ResolutionPolicy {
+            aspect_ratios: vec!["16:9".to_owned(), "16:10".to_owned()],
+            scales: vec![1.0, 1.25],
+            ..ResolutionPolicy::default()
+        };
+        let check = policy.check(client(1920, 1200), 1.25);
+        assert!(!check.warning);
+        assert_eq!(check.aspect_ratio.as_deref(), Some("16:10"));
+    }
+}
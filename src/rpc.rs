@@ -0,0 +1,235 @@
+// WebSocket 的 JSON-RPC 2.0 模式，连接时通过子协议 `jsonrpc-2.0` 或 `?protocol=jsonrpc` 启用。
+// 服务端推送的消息与默认格式相同，以通知的形式发送：`method` 为 `action` 中的 `-` 换成 `.`
+// （如 `yas.output`），`params` 为消息的其余字段。
+
+use crate::protocol::ServerMessage;
+
+use serde_json::{json, Value};
+
+pub const SUBPROTOCOL: &str = "jsonrpc-2.0";
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+// 以下为服务端自定义的错误
+// 转发的 HTTP 请求失败，`data` 为 `{status, body}`
+pub const SERVER_ERROR: i64 = -32000;
+// 已有扫描在运行，`data` 为该扫描
+pub const BUSY: i64 = -32001;
+// 令牌缺少权限，或不是扫描的控制者
+pub const FORBIDDEN: i64 = -32003;
+pub const NOT_FOUND: i64 = -32004;
+
+#[derive(Debug)]
+pub struct Request {
+    // 为空表示通知，不需要回复
+    pub id: Option<Value>,
+    pub method: String,
+    pub params: Value,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl Error {
+    pub fn new(code: i64, message: impl Into<String>) -> Error {
+        Error {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: Value) -> Error {
+        self.data = Some(data);
+        self
+    }
+}
+
+// 一条消息可以是单个请求或批量请求，无效的请求以对应的错误回复
+pub enum Incoming {
+    Single(Result<Request, Value>),
+    Batch(Vec<Result<Request, Value>>),
+    Invalid(Value),
+}
+
+pub fn parse(text: &str) -> Incoming {
+    let value: Value = match serde_json::from_str(text) {
+        Ok(value) => value,
+        Err(err) => return Incoming::Invalid(error(Value::Null, Error::new(PARSE_ERROR, err.to_string()))),
+    };
+    match value {
+        Value::Array(items) if items.is_empty() => {
+            Incoming::Invalid(error(Value::Null, Error::new(INVALID_REQUEST, "empty batch")))
+        },
+        Value::Array(items) => Incoming::Batch(items.into_iter().map(parse_request).collect()),
+        value => Incoming::Single(parse_request(value)),
+    }
+}
+
+fn parse_request(value: Value) -> Result<Request, Value> {
+    let Value::Object(mut object) = value else {
+        return Err(error(
+            Value::Null,
+            Error::new(INVALID_REQUEST, "request must be an object"),
+        ));
+    };
+    let id = object.remove("id");
+    let invalid = |message: &str| error(id.clone().unwrap_or_default(), Error::new(INVALID_REQUEST, message));
+    if object.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err(invalid("`jsonrpc` must be \"2.0\""));
+    }
+    let Some(Value::String(method)) = object.remove("method") else {
+        return Err(invalid("`method` must be a string"));
+    };
+    let params = match object.remove("params") {
+        None => Value::Null,
+        Some(params @ (Value::Object(_) | Value::Array(_))) => params,
+        Some(_) => return Err(invalid("`params` must be an object or an array")),
+    };
+    Ok(Request { id, method, params })
+}
+
+pub fn result(id: Value, result: Value) -> Value {
+    json!({"jsonrpc": "2.0", "result": result, "id": id})
+}
+
+pub fn error(id: Value, error: Error) -> Value {
+    let mut body = json!({"code": error.code, "message": error.message});
+    if let Some(data) = error.data {
+        body["data"] = data;
+    }
+    json!({"jsonrpc": "2.0", "error": body, "id": id})
+}
+
+// 一个请求的回复，通知不需要回复
+pub fn reply(id: Option<Value>, outcome: Result<Value, Error>) -> Option<Value> {
+    let id = id?;
+    Some(match outcome {
+        Ok(value) => result(id, value),
+        Err(err) => error(id, err),
+    })
+}
+
+// 批量请求的回复，其中的通知不回复，全部为通知时整个批量请求都不回复
+pub fn batch(replies: impl IntoIterator<Item = Option<Value>>) -> Option<Value> {
+    let replies: Vec<_> = replies.into_iter().flatten().collect();
+    (!replies.is_empty()).then_some(Value::Array(replies))
+}
+
+pub fn notification(message: &ServerMessage) -> Value {
+    let Value::Object(mut params) = serde_json::to_value(message).unwrap() else {
+        unreachable!("server messages are objects");
+    };
+    let method = match params.remove("action") {
+        Some(Value::String(action)) => action.replace('-', "."),
+        _ => unreachable!("server messages are tagged with `action`"),
+    };
+    json!({"jsonrpc": "2.0", "method": method, "params": Value::Object(params)})
+}
+
+// 按名称取参数，也支持按位置传参
+pub fn param<'a>(params: &'a Value, name: &str, position: usize) -> Option<&'a Value> {
+    match params {
+        Value::Object(object) => object.get(name),
+        Value::Array(items) => items.get(position),
+        _ => None,
+    }
+    .filter(|value| !value.is_null())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 以方法名作为结果回复每个请求
+    fn answer(incoming: Incoming) -> Option<Value> {
+        let respond = |request: Result<Request, Value>| match request {
+            Ok(request) => reply(request.id, Ok(Value::String(request.method))),
+            Err(response) => Some(response),
+        };
+        match incoming {
+            Incoming::Invalid(response) => Some(response),
+            Incoming::Single(request) => respond(request),
+            Incoming::Batch(requests) => batch(requests.into_iter().map(respond)),
+        }
+    }
+
+    fn code(response: &Value) -> i64 {
+        response["error"]["code"].as_i64().unwrap()
+    }
+
+    #[test]
+    fn parse_error_has_a_null_id() {
+        let response = answer(parse(r#"{"jsonrpc": "2.0", "method": "#)).unwrap();
+        assert_eq!(code(&response), PARSE_ERROR);
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(response["jsonrpc"], "2.0");
+    }
+
+    #[test]
+    fn empty_batch_is_invalid() {
+        let response = answer(parse("[]")).unwrap();
+        assert_eq!(code(&response), INVALID_REQUEST);
+        assert_eq!(response["id"], Value::Null);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        for text in [
+            r#"{"jsonrpc": "1.0", "method": "windows.list", "id": 7}"#,
+            r#"{"method": "windows.list", "id": 7}"#,
+        ] {
+            let response = answer(parse(text)).unwrap();
+            assert_eq!(code(&response), INVALID_REQUEST);
+            assert_eq!(response["id"], 7);
+        }
+        let response = answer(parse(r#"{"jsonrpc": "2.0", "method": "a", "params": 1, "id": "x"}"#)).unwrap();
+        assert_eq!(code(&response), INVALID_REQUEST);
+        assert_eq!(response["id"], "x");
+    }
+
+    #[test]
+    fn notifications_get_no_response() {
+        assert_eq!(answer(parse(r#"{"jsonrpc": "2.0", "method": "yas.update"}"#)), None);
+        let response = answer(parse(r#"{"jsonrpc": "2.0", "method": "yas.update", "id": 1}"#)).unwrap();
+        assert_eq!(response, json!({"jsonrpc": "2.0", "result": "yas.update", "id": 1}));
+    }
+
+    #[test]
+    fn batches_omit_notifications() {
+        let text = r#"[
+            {"jsonrpc": "2.0", "method": "windows.list", "id": 1},
+            {"jsonrpc": "2.0", "method": "yas.update"},
+            {"jsonrpc": "2.0", "method": "scan.result", "params": {"id": "x"}, "id": 2},
+            {"jsonrpc": "1.0", "method": "windows.list", "id": 3},
+            5
+        ]"#;
+        let response = answer(parse(text)).unwrap();
+        let responses = response.as_array().unwrap();
+        let ids: Vec<_> = responses.iter().map(|response| response["id"].clone()).collect();
+        assert_eq!(ids, [json!(1), json!(2), json!(3), Value::Null]);
+        assert_eq!(responses[1]["result"], "scan.result");
+        assert_eq!(code(&responses[2]), INVALID_REQUEST);
+        assert_eq!(code(&responses[3]), INVALID_REQUEST);
+        // 全部为通知时不回复
+        let text = r#"[{"jsonrpc": "2.0", "method": "a"}, {"jsonrpc": "2.0", "method": "b"}]"#;
+        assert_eq!(answer(parse(text)), None);
+    }
+
+    #[test]
+    fn reads_named_and_positional_params() {
+        let named = json!({"argv": "--dump", "id": null});
+        assert_eq!(param(&named, "argv", 0), Some(&json!("--dump")));
+        assert_eq!(param(&named, "id", 1), None);
+        let positional = json!(["--dump"]);
+        assert_eq!(param(&positional, "argv", 0), Some(&json!("--dump")));
+        assert_eq!(param(&Value::Null, "argv", 0), None);
+    }
+}
@@ -0,0 +1,502 @@
+use crate::{
+    asciicast::{self, Recorder},
+    auth::Scope,
+    config::Config,
+    encoding::OutputEncoding,
+    events::EventBus,
+    hub::ScanHub,
+    protocol::{ServerMessage, Session},
+};
+
+use chrono::{Local, SecondsFormat};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    process::Command,
+    sync::{mpsc, Notify},
+};
+use uuid::Uuid;
+
+// 每次扫描的记录，保存在扫描目录下的 `scan.json`
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct ScanInfo {
+    pub id: Uuid,
+    pub program: String,
+    pub argv: Vec<String>,
+    pub pid: Option<u32>,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub exit_code: Option<i32>,
+    pub success: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stream::Stdout => write!(f, "stdout"),
+            Stream::Stderr => write!(f, "stderr"),
+        }
+    }
+}
+
+// yas 输出的一行，`seq` 在一次扫描内从 0 开始递增
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputLine {
+    pub seq: u64,
+    pub time: String,
+    pub stream: Stream,
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub enum ScanError {
+    // 已经有一个扫描在运行
+    Busy(Box<ScanInfo>),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanError::Busy(info) => write!(f, "scan {} is already running", info.id),
+            ScanError::Io(err) => write!(f, "failed to run yas: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ScanError {}
+
+impl From<std::io::Error> for ScanError {
+    fn from(err: std::io::Error) -> Self {
+        ScanError::Io(err)
+    }
+}
+
+// yas 可能输出的结果文件，按顺序查找
+const RESULT_FILES: [&str; 2] = ["mona.json", "good.json"];
+
+// 全局只允许同时运行一个 yas，多余的请求直接以 busy 拒绝。
+// 每次扫描在 `root` 下拥有独立的目录，存放参数、日志、退出状态和 yas 的输出。
+// 输出经由 `hub` 广播给所有订阅的客户端，扫描的开始、结束与输出同时发布到 `events`。
+pub struct ScanManager {
+    root: PathBuf,
+    retention: usize,
+    record_asciicast: bool,
+    encoding: OutputEncoding,
+    current: Mutex<Option<ScanInfo>>,
+    hub: Arc<ScanHub>,
+    events: Arc<EventBus>,
+}
+
+// 占用扫描名额，未运行就被丢弃或运行结束时释放
+pub struct ScanSlot<'a> {
+    manager: &'a ScanManager,
+    id: Uuid,
+    cancel: Arc<Notify>,
+}
+
+impl Drop for ScanSlot<'_> {
+    fn drop(&mut self) {
+        // 参数未通过校验等情况下扫描没有运行，同样不再接受订阅
+        self.manager.hub.close();
+        *self.manager.current.lock().unwrap() = None;
+    }
+}
+
+impl ScanSlot<'_> {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        self.manager.scan_dir(&self.id)
+    }
+
+    // 运行 yas 直到退出或被控制者取消，stdout 与 stderr 的每一行都会写入日志并广播
+    pub async fn run(self, args: &[String]) -> Result<ScanInfo, ScanError> {
+        let dir = match self.prepare(args) {
+            Ok(dir) => dir,
+            Err(err) => return Err(self.fail(err)),
+        };
+        let program = self.snapshot().program;
+
+        let spawned = Command::new(&program)
+            .args(args)
+            .current_dir(&dir)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(err) => return Err(self.fail(err)),
+        };
+
+        if let Some(info) = self.manager.current.lock().unwrap().as_mut() {
+            info.pid = child.id();
+        }
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let (line_tx, line_rx) = mpsc::channel(16);
+        let log = match self.manager.open_log(&dir, &program, args).await {
+            Ok(log) => log,
+            Err(err) => return Err(self.fail(err)),
+        };
+
+        let wait = async {
+            let cancelled = tokio::select! {
+                status = child.wait() => Some(status),
+                _ = self.cancel.notified() => None,
+            };
+            match cancelled {
+                Some(status) => status,
+                None => {
+                    println!("扫描 {} 已被取消", self.id);
+                    let _ = child.start_kill();
+                    child.wait().await
+                },
+            }
+        };
+        let (_, _, _, status) = tokio::join!(
+            forward_lines(stdout, Stream::Stdout, self.manager.encoding, line_tx.clone()),
+            forward_lines(stderr, Stream::Stderr, self.manager.encoding, line_tx),
+            self.manager.write_log(log, line_rx),
+            wait
+        );
+        match status {
+            Ok(status) => self.complete(status.code(), status.success()),
+            Err(err) => Err(self.fail(err)),
+        }
+    }
+
+    // 不运行 yas，而是按 asciicast 录像回放其输出，用于在没有游戏的情况下测试网页端
+    pub async fn replay(self, cast: &Path, speed: f64, args: &[String]) -> Result<ScanInfo, ScanError> {
+        let dir = match self.prepare(args) {
+            Ok(dir) => dir,
+            Err(err) => return Err(self.fail(err)),
+        };
+        let events = match asciicast::read(cast) {
+            Ok((_, events)) => events,
+            Err(err) => return Err(self.fail(std::io::Error::other(err.to_string()))),
+        };
+
+        let (text_tx, mut text_rx) = mpsc::channel(16);
+        let (line_tx, line_rx) = mpsc::channel(16);
+        let log = match self.manager.open_log(&dir, &cast.display().to_string(), args).await {
+            Ok(log) => log,
+            Err(err) => return Err(self.fail(err)),
+        };
+
+        let play = async {
+            tokio::select! {
+                _ = asciicast::play_lines(&events, speed, text_tx) => true,
+                _ = self.cancel.notified() => false,
+            }
+        };
+        let (success, _, _) = tokio::join!(
+            play,
+            async move {
+                while let Some(text) = text_rx.recv().await {
+                    let _ = line_tx.send((Stream::Stdout, text)).await;
+                }
+            },
+            self.manager.write_log(log, line_rx)
+        );
+        self.complete(success.then_some(0), success)
+    }
+
+    // 创建扫描目录并记录本次的参数
+    fn prepare(&self, args: &[String]) -> Result<PathBuf, ScanError> {
+        let dir = self.dir();
+        fs::create_dir_all(&dir)?;
+        if let Some(info) = self.manager.current.lock().unwrap().as_mut() {
+            info.argv = args.to_vec();
+        }
+        let info = self.snapshot();
+        self.manager.save(&info)?;
+        self.manager
+            .events
+            .publish(Scope::Scan, &ServerMessage::Scan { data: Box::new(info) });
+        Ok(dir)
+    }
+
+    // 记录退出状态并释放扫描名额，`exit_code` 为空表示 yas 没能启动或无法获取退出状态
+    fn complete(self, exit_code: Option<i32>, success: bool) -> Result<ScanInfo, ScanError> {
+        let manager = self.manager;
+        manager.hub.close();
+
+        let mut info = self.snapshot();
+        info.finished_at = Some(Local::now().to_rfc3339());
+        info.exit_code = exit_code;
+        info.success = Some(success);
+        // 扫描目录可能没能创建，保存失败时仍然通知客户端扫描已结束
+        let saved = manager.save(&info);
+        manager.events.publish(
+            Scope::Scan,
+            &ServerMessage::Scan {
+                data: Box::new(info.clone()),
+            },
+        );
+        drop(self);
+
+        manager.prune();
+        saved?;
+        Ok(info)
+    }
+
+    // 扫描没能正常进行，记录失败并释放扫描名额后返回原来的错误
+    fn fail(self, err: impl Into<ScanError>) -> ScanError {
+        let id = self.id;
+        if let Err(save_err) = self.complete(None, false) {
+            eprintln!("保存扫描 {id} 的状态失败：{save_err}");
+        }
+        err.into()
+    }
+
+    fn snapshot(&self) -> ScanInfo {
+        self.manager.current().expect("slot is held")
+    }
+}
+
+// 一次扫描的日志文件，以及可选的 asciicast 录像
+struct ScanLog {
+    file: tokio::fs::File,
+    cast: Option<Recorder>,
+}
+
+impl ScanManager {
+    pub fn new(config: &Config, events: Arc<EventBus>) -> ScanManager {
+        ScanManager {
+            root: config.data_dir().join("scans"),
+            retention: config.scan_retention.max(1),
+            record_asciicast: config.record_asciicast,
+            encoding: OutputEncoding::from_label(&config.output_encoding).unwrap_or_else(|| {
+                eprintln!("未知的编码 `{}`，将自动检测 yas 输出的编码", config.output_encoding);
+                OutputEncoding::Auto
+            }),
+            current: Mutex::new(None),
+            hub: Arc::new(ScanHub::new(config.output_buffer_lines)),
+            events,
+        }
+    }
+
+    pub fn hub(&self) -> &Arc<ScanHub> {
+        &self.hub
+    }
+
+    pub fn current(&self) -> Option<ScanInfo> {
+        self.current.lock().unwrap().clone()
+    }
+
+    pub fn begin(&self, program: &Path) -> Result<ScanSlot<'_>, ScanError> {
+        let mut current = self.current.lock().unwrap();
+        if let Some(info) = current.as_ref() {
+            return Err(ScanError::Busy(Box::new(info.clone())));
+        }
+        let id = Uuid::new_v4();
+        *current = Some(ScanInfo {
+            id,
+            program: program.display().to_string(),
+            argv: Vec::new(),
+            pid: None,
+            started_at: Local::now().to_rfc3339(),
+            finished_at: None,
+            exit_code: None,
+            success: None,
+        });
+        let cancel = self.hub.open(id);
+        Ok(ScanSlot {
+            manager: self,
+            id,
+            cancel,
+        })
+    }
+
+    fn publish(&self, stream: Stream, text: String) -> OutputLine {
+        let line = self.hub.publish(|seq| OutputLine {
+            seq,
+            time: Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+            stream,
+            text,
+        });
+        let message = ServerMessage::output(line.clone(), &Session::latest());
+        self.events.publish(Scope::Scan, &message);
+        line
+    }
+
+    async fn open_log(&self, dir: &Path, program: &str, args: &[String]) -> std::io::Result<ScanLog> {
+        let file = tokio::fs::File::create(dir.join("output.log")).await?;
+        let cast = if self.record_asciicast {
+            let command = std::iter::once(program.to_owned()).chain(args.iter().cloned());
+            Some(Recorder::create(&dir.join("session.cast"), &command.collect::<Vec<_>>().join(" ")).await?)
+        } else {
+            None
+        };
+        Ok(ScanLog { file, cast })
+    }
+
+    async fn write_log(&self, mut log: ScanLog, mut lines: mpsc::Receiver<(Stream, String)>) {
+        while let Some((stream, text)) = lines.recv().await {
+            println!("{text}");
+            let line = self.publish(stream, text);
+            let record = format!("{} [{}] {}\n", line.time, line.stream, line.text);
+            if let Err(err) = log.file.write_all(record.as_bytes()).await {
+                eprintln!("写入扫描日志失败：{err}");
+            }
+            if let Some(cast) = log.cast.as_mut() {
+                if let Err(err) = cast.record_line(&line.text).await {
+                    eprintln!("写入 asciicast 录像失败：{err}");
+                }
+            }
+        }
+        let _ = log.file.flush().await;
+        if let Some(cast) = log.cast.as_mut() {
+            let _ = cast.flush().await;
+        }
+    }
+
+    fn scan_dir(&self, id: &Uuid) -> PathBuf {
+        self.root.join(id.to_string())
+    }
+
+    fn save(&self, info: &ScanInfo) -> Result<(), std::io::Error> {
+        let file = fs::File::create(self.scan_dir(&info.id).join("scan.json"))?;
+        serde_json::to_writer_pretty(file, info)?;
+        Ok(())
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<ScanInfo> {
+        let file = fs::File::open(self.scan_dir(id).join("scan.json")).ok()?;
+        serde_json::from_reader(file).ok()
+    }
+
+    // 所有扫描记录，最新的在前
+    pub fn list(&self) -> Vec<ScanInfo> {
+        let Ok(entries) = fs::read_dir(&self.root) else {
+            return Vec::new();
+        };
+        let mut scans: Vec<ScanInfo> = entries
+            .flatten()
+            .filter_map(|entry| Uuid::parse_str(&entry.file_name().to_string_lossy()).ok())
+            .filter_map(|id| self.get(&id))
+            .collect();
+        scans.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+        scans
+    }
+
+    // 扫描结果，即 yas 在扫描目录下输出的 json
+    pub fn result(&self, id: &Uuid) -> Option<Value> {
+        let dir = self.scan_dir(id);
+        RESULT_FILES.iter().find_map(|name| {
+            let file = fs::File::open(dir.join(name)).ok()?;
+            serde_json::from_reader(file).ok()
+        })
+    }
+
+    // 最近一次有结果的扫描
+    pub fn latest_result(&self) -> Option<Value> {
+        self.list().iter().find_map(|scan| self.result(&scan.id))
+    }
+
+    fn prune(&self) {
+        for scan in self.list().iter().skip(self.retention) {
+            if let Err(err) = fs::remove_dir_all(self.scan_dir(&scan.id)) {
+                eprintln!("删除扫描记录 {} 失败：{err}", scan.id);
+            }
+        }
+    }
+}
+
+async fn forward_lines(
+    reader: impl AsyncRead + Unpin,
+    stream: Stream,
+    encoding: OutputEncoding,
+    output: mpsc::Sender<(Stream, String)>,
+) {
+    let mut reader = BufReader::new(reader);
+    let mut buffer = Vec::new();
+    loop {
+        buffer.clear();
+        match reader.read_until(b'\n', &mut buffer).await {
+            Ok(0) => break,
+            Ok(_) => {
+                let line = buffer.strip_suffix(b"\n").unwrap_or(&buffer);
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                let _ = output.send((stream, encoding.decode(line))).await;
+            },
+            Err(err) => {
+                eprintln!("读取 yas 输出失败：{err}");
+                break;
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hub::Role;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn failed_preparation_finishes_the_scan() {
+        // 数据目录下的 `scans` 是文件，无法创建扫描目录
+        let root = std::env::temp_dir().join(format!("frostflake-scan-{}", Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("scans"), b"").unwrap();
+        let config = Config {
+            data_dir: Some(root.clone()),
+            ..Config::default()
+        };
+        let events = Arc::new(EventBus::new(16));
+        let manager = ScanManager::new(&config, events.clone());
+
+        let slot = manager.begin(Path::new("yas.exe")).unwrap();
+        let id = slot.id();
+        let mut subscription = manager
+            .hub()
+            .subscribe(&id, 0, Uuid::new_v4(), Role::Controller)
+            .unwrap();
+        let mut live = subscription.live.take().unwrap();
+
+        assert!(matches!(slot.run(&[]).await, Err(ScanError::Io(_))));
+        assert!(manager.current().is_none());
+        // 广播中心已关闭，订阅者收到结束，之后只能以观看者的身份订阅
+        assert!(live.recv().await.is_err());
+        let viewer = manager
+            .hub()
+            .subscribe(&id, 0, Uuid::new_v4(), Role::Controller)
+            .unwrap();
+        assert_eq!(viewer.role, Role::Viewer);
+
+        let backlog = events.subscribe(Some(0));
+        let finished: Value = serde_json::from_str(&backlog.events.last().unwrap().json).unwrap();
+        assert_eq!(finished["action"], "scan");
+        assert_eq!(finished["data"]["id"], json!(id));
+        assert!(finished["data"]["finished_at"].is_string());
+        assert_eq!(finished["data"]["success"], false);
+
+        // 失败的扫描不会占用名额
+        drop(subscription);
+        drop(viewer);
+        assert!(manager.begin(Path::new("yas.exe")).is_ok());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
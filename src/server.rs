@@ -1,39 +1,79 @@
 use crate::{
+    auth::{Forbidden, Grant, Scope, TokenStore},
+    capture::{self, CaptureError, CaptureOptions},
+    config::Config,
+    doctor,
+    events::{Event, EventBus},
+    game::{GameEvent, GameWatcher, GameWindow},
+    hub::{HubError, Role, Subscription},
+    input::{self, InputController, InputError, InputRequest},
+    locator::{self, GameLocator, LaunchError, LaunchRequest},
+    outbox::{Outbox, OutputQueue, Task},
+    platform::{
+        active_console_window, capture as platform_capture, enable_virtual_terminal_sequences, input_injector,
+        notify_message, registry, system_info, window_manager, Capture, WindowInfo, WindowManager,
+    },
+    protocol::{
+        negotiate, parse_client_message, ApiRequest, ApiResponse, AttachRequest, AttachResponse, CancelResponse,
+        Capability, ClientAction, ErrorCode, EventsMissed, ServerMessage, Session, WindowEvent, YasEvent, YasUpdate,
+    },
+    resolution::ResolutionPolicy,
+    rpc::{self, Incoming},
+    scan::{ScanError, ScanInfo, ScanManager},
     utils::{current_dir_file, prompt_user},
-    windows::{active_console_window, active_window, enable_virtual_terminal_sequences, list_windows, notify_message},
+    window_control::{self, ActionStatus, WindowAction, WindowRequest},
+    window_filter::{allowed, ListOptions, WindowRule},
+    yas_args::{split_argv, ArgError, YasArgsPolicy},
 };
 
 use axum::{
-    body::Body,
+    body::{Body, Bytes},
     extract::{
         ws::{Message, WebSocket},
-        State, WebSocketUpgrade,
+        FromRef, Path, Query, State, WebSocketUpgrade,
+    },
+    http::{
+        header::{ACCEPT, CONTENT_TYPE},
+        HeaderMap, Method, Request, StatusCode,
+    },
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        IntoResponse, Response,
     },
-    http::{HeaderMap, Method, StatusCode},
-    response::Response,
     routing::{get, options, patch, post},
     Json, Router,
 };
 
 use chrono::DateTime;
-use futures::{SinkExt, StreamExt};
+use futures::{stream::SplitSink, SinkExt, StreamExt};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::{
-    collections::HashSet,
+    convert::Infallible,
     env,
     error::Error,
     fs,
-    io::{BufRead, BufReader, Write},
-    path::Path,
-    sync::{Arc, Mutex},
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
 };
-use tokio::sync::mpsc;
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc, oneshot,
+    },
+    task::JoinHandle,
+    time::{Instant, MissedTickBehavior},
+};
+use tower::ServiceExt;
 use tower_http::cors::{Any, CorsLayer};
 use uuid::Uuid;
 
-#[derive(PartialEq, Clone, Copy)]
-enum YasUpdateState {
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum YasUpdateState {
     Prechecking,
     Downloading,
     Done,
@@ -41,29 +81,66 @@ enum YasUpdateState {
 }
 
 struct AppState {
-    authorized_tokens: Mutex<HashSet<Uuid>>,
+    config: Config,
+    tokens: TokenStore,
+    events: Arc<EventBus>,
     yas_update_state: Mutex<YasUpdateState>,
     yas_download_state: Mutex<(usize, usize)>,
+    scans: ScanManager,
+    games: Arc<GameWatcher>,
+    locator: GameLocator,
+    windows: Arc<dyn WindowManager>,
+    capture: Arc<dyn Capture>,
+    input: Arc<InputController>,
+    replay: Option<Replay>,
+    // 是否同意为某个来源生成令牌，第二个参数为默认权限以外另外请求的权限
+    consent: fn(&str, &[Scope]) -> bool,
+    // WebSocket 的 `api` 请求在进程内交给它处理
+    router: OnceLock<Router>,
 }
 
-impl AppState {
-    fn insert_token(&self, token: Uuid) {
-        self.authorized_tokens.lock().unwrap().insert(token);
-    }
-    fn contains_token(&self, token: &Uuid) -> bool {
-        return self.authorized_tokens.lock().unwrap().contains(token);
+impl FromRef<Arc<AppState>> for TokenStore {
+    fn from_ref(state: &Arc<AppState>) -> Self {
+        state.tokens.clone()
     }
+}
+
+// `replay --serve` 模式下用录像代替 yas
+pub struct Replay {
+    pub file: PathBuf,
+    pub speed: f64,
+}
+
+impl AppState {
     fn get_yas_update_state(&self) -> YasUpdateState {
         return *self.yas_update_state.lock().unwrap();
     }
     fn set_yas_update_state(&self, state: YasUpdateState) {
         *self.yas_update_state.lock().unwrap() = state;
+        let update = YasUpdate {
+            state,
+            downloaded: None,
+            total: None,
+        };
+        self.events
+            .publish(Scope::Scan, &ServerMessage::YasUpdate { data: update });
     }
     fn get_download_state(&self) -> (usize, usize) {
         return *self.yas_download_state.lock().unwrap();
     }
     fn set_download_state(&self, state: (usize, usize)) {
-        *self.yas_download_state.lock().unwrap() = state;
+        let previous = std::mem::replace(&mut *self.yas_download_state.lock().unwrap(), state);
+        // 每下载 1%（大小未知时每 1 MiB）发布一次进度
+        let step = if state.1 > 0 { state.1 / 100 } else { 1 << 20 }.max(1);
+        if previous.0 / step != state.0 / step || previous.1 != state.1 {
+            let update = YasUpdate {
+                state: YasUpdateState::Downloading,
+                downloaded: Some(state.0),
+                total: Some(state.1),
+            };
+            self.events
+                .publish(Scope::Scan, &ServerMessage::YasUpdate { data: update });
+        }
     }
 }
 
@@ -111,22 +188,42 @@ async fn api_root() -> Json<Value> {
     }))
 }
 
-async fn api_token(header_map: HeaderMap, State(state): State<Arc<AppState>>) -> Response {
+#[derive(Debug, Default, Deserialize)]
+struct TokenRequest {
+    // 默认权限以外另外请求的权限，无法识别的会被忽略
+    #[serde(default)]
+    scopes: Vec<Value>,
+}
+
+// 原版不带请求体，请求体无法解析时按没有请求体处理
+async fn api_token(header_map: HeaderMap, State(state): State<Arc<AppState>>, body: Bytes) -> Response {
     let url = header_map.get("Origin").unwrap().to_str().unwrap();
-    notify_message("frostflake", &format!("收到来自 {url} 的新请求")).unwrap();
-    let message = format!("来自 {url} 的请求\n确定要生成新的令牌吗？[Y/N] ");
-    active_console_window().unwrap();
-    if prompt_user(&message) == "Y" {
+    let request: TokenRequest = serde_json::from_slice(&body).unwrap_or_default();
+    let mut extra: Vec<Scope> = request
+        .scopes
+        .into_iter()
+        .filter_map(|scope| serde_json::from_value(scope).ok())
+        .filter(|scope| Scope::OPTIONAL.contains(scope))
+        .collect();
+    extra.dedup();
+    if (state.consent)(url, &extra) {
         let id = Uuid::new_v4();
-        state.insert_token(id);
+        let scopes: Vec<Scope> = Scope::DEFAULT.into_iter().chain(extra).collect();
+        state.tokens.insert(Grant {
+            token: id,
+            origin: url.to_owned(),
+            scopes: scopes.clone(),
+        });
+        let system = system_info();
         response_json(
             StatusCode::ACCEPTED,
             json!({
-                "hwnd": 114514, // TODO!
+                "hwnd": system.console_hwnd,
                 "origin": url,
-                "swapEffectUpgrade": false, // TODO!
+                "scopes": scopes,
+                "swapEffectUpgrade": system.swap_effect_upgrade,
                 "token": id.to_string(),
-                "winver": 11 // TODO!
+                "winver": system.winver()
             }),
         )
     } else {
@@ -134,27 +231,424 @@ async fn api_token(header_map: HeaderMap, State(state): State<Arc<AppState>>) ->
     }
 }
 
-async fn api_api_windows() -> Json<Value> {
-    let windows = list_windows().unwrap();
-    Json(json!(windows))
+// 在控制台询问用户是否为该来源生成令牌
+fn console_consent(origin: &str, extra: &[Scope]) -> bool {
+    notify_message("frostflake", &format!("收到来自 {origin} 的新请求")).unwrap();
+    let input = match extra.contains(&Scope::Input) {
+        true => "，并允许它控制鼠标与键盘",
+        false => "",
+    };
+    let message = format!("来自 {origin} 的请求\n确定要生成新的令牌{input}吗？[Y/N] ");
+    active_console_window().unwrap();
+    prompt_user(&message) == "Y"
+}
+
+// 只返回允许列表中、且满足查询条件的窗口。与原版相同，不需要令牌也可以列出窗口，
+// 但所属进程、状态、显示器与扫描检查只在令牌有 `windows` 权限时返回，也只有这时才能按进程筛选
+async fn api_api_windows(
+    grant: Result<Grant, Response>,
+    Query(mut rule): Query<WindowRule>,
+    Query(options): Query<ListOptions>,
+    State(state): State<Arc<AppState>>,
+) -> Response<Body> {
+    let detailed = grant.is_ok_and(|grant| grant.has(Scope::Windows));
+    if !detailed {
+        rule.process = None;
+    }
+    let windows = match state.windows.list() {
+        Ok(windows) => windows,
+        Err(err) => return response_json(StatusCode::INTERNAL_SERVER_ERROR, json!({"msg": err.to_string()})),
+    };
+    let policy = ResolutionPolicy::load(&YasReleaseInfo::read_from_file().unwrap_or_default().version);
+    let windows: Vec<_> = windows
+        .into_iter()
+        .filter(|window| allowed(&state.config.window_allowlist, window))
+        .filter(|window| rule.matches(window) && (window.visible || !options.visible_only))
+        .map(|window| match detailed {
+            true => {
+                let mut value = json!(window);
+                value["scan"] = json!(policy.check_window(&window));
+                value
+            },
+            false => json!({
+                "class_name": window.class_name,
+                "title": window.title,
+                "hwnd": window.hwnd,
+                "height": window.height,
+                "width": window.width,
+                "x": window.x,
+                "y": window.y,
+            }),
+        })
+        .collect();
+    response_json(StatusCode::OK, json!(windows))
+}
+
+// 显示器的位置、工作区、DPI 与缩放比例
+async fn api_displays(grant: Grant, State(state): State<Arc<AppState>>) -> Result<Response<Body>, Forbidden> {
+    grant.require(Scope::Windows)?;
+    Ok(match state.windows.displays() {
+        Ok(displays) => response_json(StatusCode::OK, json!(displays)),
+        Err(err) => response_json(StatusCode::INTERNAL_SERVER_ERROR, json!({"msg": err.to_string()})),
+    })
 }
 
-async fn api_patch_windows(uri: axum::http::Uri) -> Json<Value> {
+// 请求体为空时只切换到前台并返回 `{}`，与原版相同，不需要令牌；其他操作需要 `windows` 权限。
+// 全部操作成功时返回 200，否则返回 422
+async fn api_patch_windows(
+    grant: Result<Grant, Response>,
+    uri: axum::http::Uri,
+    State(state): State<Arc<AppState>>,
+    body: Bytes,
+) -> Response<Body> {
     let hwnd = uri.path().split('/').next_back().unwrap();
-    if hwnd != "null" {
-        let hwnd: usize = hwnd.parse().unwrap();
-        active_window(hwnd).unwrap()
+    if hwnd == "null" {
+        return response_json(StatusCode::OK, json!({}));
     }
-    Json(json!({}))
+    let Ok(hwnd) = hwnd.parse::<usize>() else {
+        return response_json(StatusCode::BAD_REQUEST, json!({"msg": "invalid hwnd"}));
+    };
+    let request = match body.is_empty() {
+        true => Ok(WindowRequest::default()),
+        false => serde_json::from_slice::<WindowRequest>(&body),
+    };
+    let request = match request {
+        Ok(request) => request,
+        Err(err) => return response_json(StatusCode::BAD_REQUEST, json!({"msg": err.to_string()})),
+    };
+    // 没有列出操作时为原版的切换前台请求，成功时同样返回 `{}`
+    let legacy = request.actions.is_empty();
+    if !legacy {
+        match grant.map(|grant| grant.require(Scope::Windows)) {
+            Ok(Ok(())) => {},
+            Ok(Err(forbidden)) => return forbidden.into_response(),
+            Err(rejection) => return rejection,
+        }
+    }
+    let actions = request.actions();
+    // 不在允许列表中的窗口与不存在的窗口同样对待
+    let listed = match state.windows.list() {
+        Ok(windows) => windows
+            .iter()
+            .any(|window| window.hwnd == hwnd && allowed(&state.config.window_allowlist, window)),
+        Err(err) => return response_json(StatusCode::INTERNAL_SERVER_ERROR, json!({"msg": err.to_string()})),
+    };
+    if !listed {
+        return response_json(StatusCode::NOT_FOUND, json!({"msg": "window not found"}));
+    }
+    let windows = state.windows.clone();
+    let (actions, results) = tokio::task::spawn_blocking(move || {
+        let results = window_control::apply(windows.as_ref(), hwnd, &actions);
+        (actions, results)
+    })
+    .await
+    .unwrap();
+    let focused = actions
+        .iter()
+        .zip(&results)
+        .any(|(action, result)| matches!(action, WindowAction::Focus) && result.status == ActionStatus::Done);
+    if focused {
+        let event = ServerMessage::Window {
+            data: Box::new(WindowEvent::Activated { hwnd }),
+        };
+        state.events.publish(Scope::Windows, &event);
+    }
+    let code = match results.iter().all(|result| result.status == ActionStatus::Done) {
+        true => StatusCode::OK,
+        false => StatusCode::UNPROCESSABLE_ENTITY,
+    };
+    let body = match legacy && code == StatusCode::OK {
+        true => json!({}),
+        false => json!({"results": results}),
+    };
+    response_json(code, body)
+}
+
+// 允许列表中句柄为 `hwnd` 的窗口
+fn find_window(state: &AppState, hwnd: &str) -> Result<WindowInfo, CaptureError> {
+    let hwnd: usize = hwnd
+        .parse()
+        .map_err(|_| CaptureError::Invalid("invalid hwnd".to_owned()))?;
+    state
+        .windows
+        .list()
+        .map_err(CaptureError::Failed)?
+        .into_iter()
+        .find(|window| window.hwnd == hwnd && allowed(&state.config.window_allowlist, window))
+        .ok_or(CaptureError::NotFound)
+}
+
+fn capture_error(err: CaptureError) -> Response<Body> {
+    let code = match err {
+        CaptureError::Invalid(_) => StatusCode::BAD_REQUEST,
+        CaptureError::NotFound => StatusCode::NOT_FOUND,
+        CaptureError::Minimized => StatusCode::CONFLICT,
+        CaptureError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    response_json(code, json!({"msg": err.to_string()}))
+}
+
+async fn api_window_capture(
+    grant: Grant,
+    Path(hwnd): Path<String>,
+    Query(options): Query<CaptureOptions>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response<Body>, Forbidden> {
+    grant.require(Scope::Windows)?;
+    let window = match options.validate().and_then(|_| find_window(&state, &hwnd)) {
+        Ok(window) => window,
+        Err(err) => return Ok(capture_error(err)),
+    };
+    let capture = state.capture.clone();
+    let format = options.format;
+    let image = tokio::task::spawn_blocking(move || capture::snapshot(capture.as_ref(), &window, &options))
+        .await
+        .unwrap();
+    Ok(match image {
+        Ok(image) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", format.content_type())
+            .header("Cache-Control", "no-store")
+            .body(Body::from(image))
+            .unwrap(),
+        Err(err) => capture_error(err),
+    })
+}
+
+// 浏览器的 WebSocket 无法设置请求头，令牌通过 `?token=` 传递
+async fn api_window_capture_stream(
+    grant: Grant,
+    Path(hwnd): Path<String>,
+    Query(options): Query<CaptureOptions>,
+    ws: WebSocketUpgrade,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response<Body>, Forbidden> {
+    grant.require(Scope::Windows)?;
+    let window = match options.validate().and_then(|_| find_window(&state, &hwnd)) {
+        Ok(window) => window,
+        Err(err) => return Ok(capture_error(err)),
+    };
+    let (windows, capture) = (state.windows.clone(), state.capture.clone());
+    Ok(ws.on_upgrade(move |socket| capture::stream(socket, windows, capture, window.hwnd, options)))
 }
 
-async fn api_ws(uri: axum::http::Uri, ws: WebSocketUpgrade, State(state): State<Arc<AppState>>) -> Response {
-    if let Some(uuid_str) = uri.path().split('/').next_back() {
-        if let Ok(uuid) = Uuid::parse_str(uuid_str) {
-            if state.contains_token(&uuid) {
-                return ws.on_upgrade(handle_ws);
+// 依次执行请求中的操作，全部成功时返回 200，否则返回 422
+async fn api_window_input(
+    grant: Grant,
+    Path(hwnd): Path<String>,
+    State(state): State<Arc<AppState>>,
+    body: Bytes,
+) -> Result<Response<Body>, Forbidden> {
+    grant.require(Scope::Input)?;
+    let request: InputRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(err) => return Ok(response_json(StatusCode::BAD_REQUEST, json!({"msg": err.to_string()}))),
+    };
+    let window = match find_window(&state, &hwnd) {
+        Ok(window) => window,
+        Err(err) => return Ok(capture_error(err)),
+    };
+    if window.minimized {
+        return Ok(capture_error(CaptureError::Minimized));
+    }
+    let actions = match input::plan(&request.actions, window.client) {
+        Ok(actions) => actions,
+        Err(msg) => return Ok(response_json(StatusCode::BAD_REQUEST, json!({"msg": msg}))),
+    };
+    let controller = state.input.clone();
+    let results = tokio::task::spawn_blocking(move || controller.run(actions))
+        .await
+        .unwrap();
+    Ok(match results {
+        Ok(results) => {
+            let code = match results.iter().all(|result| result.status == ActionStatus::Done) {
+                true => StatusCode::OK,
+                false => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            response_json(code, json!({"results": results}))
+        },
+        Err(InputError::Busy) => response_json(
+            StatusCode::TOO_MANY_REQUESTS,
+            json!({"msg": "another input sequence is running"}),
+        ),
+        Err(InputError::Stopped) => response_json(
+            StatusCode::LOCKED,
+            json!({"msg": format!("input stopped, press {} to resume", state.input.hotkey())}),
+        ),
+    })
+}
+
+async fn api_input(grant: Grant, State(state): State<Arc<AppState>>) -> Result<Json<Value>, Forbidden> {
+    grant.require(Scope::Input)?;
+    Ok(Json(json!({
+        "stopped": state.input.stopped(),
+        "hotkey": state.input.hotkey(),
+        "max_events_per_second": state.input.max_events_per_second(),
+    })))
+}
+
+// 以 SSE 推送与 WebSocket 相同的消息，事件类型为消息的 `action`。
+// 只推送令牌有权限查看的事件，`Last-Event-ID` 用于断线后补齐错过的事件。
+async fn api_events(
+    grant: Grant,
+    headers: HeaderMap,
+    State(state): State<Arc<AppState>>,
+) -> Sse<impl futures::Stream<Item = Result<SseEvent, Infallible>>> {
+    let last_event_id = headers
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok());
+    let backlog = state.events.subscribe(last_event_id);
+
+    let missed = (backlog.missed > 0).then(|| events_missed(backlog.missed));
+    let replayed: Vec<SseEvent> = missed
+        .into_iter()
+        .chain(
+            backlog
+                .events
+                .iter()
+                .filter(|event| grant.has(event.scope))
+                .map(|event| sse_event(event)),
+        )
+        .collect();
+    let live = futures::stream::unfold(backlog.live, move |mut live| {
+        let grant = grant.clone();
+        async move {
+            loop {
+                match live.recv().await {
+                    Ok(event) if grant.has(event.scope) => return Some((sse_event(&event), live)),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(n)) => return Some((events_missed(n), live)),
+                    Err(RecvError::Closed) => return None,
+                }
             }
         }
+    });
+    let stream = futures::stream::iter(replayed).chain(live).map(Ok);
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+fn sse_event(event: &Event) -> SseEvent {
+    SseEvent::default()
+        .id(event.id.to_string())
+        .event(&event.action)
+        .data(&event.json)
+}
+
+// 不带 id，不影响客户端记录的 `Last-Event-ID`
+fn events_missed(missed: u64) -> SseEvent {
+    let message = ServerMessage::EventsMissed {
+        data: EventsMissed { missed },
+    };
+    SseEvent::default()
+        .event("events-missed")
+        .data(serde_json::to_string(&message).unwrap())
+}
+
+// 最近检测到的游戏窗口
+async fn api_game(grant: Grant, State(state): State<Arc<AppState>>) -> Result<Response<Body>, Forbidden> {
+    grant.require(Scope::Windows)?;
+    Ok(match state.games.current() {
+        Some(window) => {
+            let policy = ResolutionPolicy::load(&YasReleaseInfo::read_from_file().unwrap_or_default().version);
+            let mut value = json!(window);
+            value["scan"] = json!(policy.check(window.client, window.scale));
+            response_json(StatusCode::OK, value)
+        },
+        None => response_json(StatusCode::NOT_FOUND, json!({"msg": "game not found"})),
+    })
+}
+
+// 本机安装的游戏客户端
+async fn api_games(grant: Grant, State(state): State<Arc<AppState>>) -> Result<Json<Value>, Forbidden> {
+    grant.require(Scope::Windows)?;
+    let games = tokio::task::spawn_blocking(move || state.locator.locate())
+        .await
+        .unwrap();
+    Ok(Json(json!(games)))
+}
+
+// 启动游戏。不等待时返回 202；等待时在窗口出现后返回 200，超时返回 504
+async fn api_launch_game(
+    grant: Grant,
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    body: Bytes,
+) -> Result<Response<Body>, Forbidden> {
+    grant.require(Scope::Windows)?;
+    let request: LaunchRequest = match body.is_empty() {
+        true => LaunchRequest::default(),
+        false => match serde_json::from_slice(&body) {
+            Ok(request) => request,
+            Err(err) => return Ok(response_json(StatusCode::BAD_REQUEST, json!({"msg": err.to_string()}))),
+        },
+    };
+    let timeout_ms = request.timeout_ms.unwrap_or(locator::DEFAULT_WAIT_MS);
+    if timeout_ms > locator::MAX_WAIT_MS {
+        let msg = format!("timeout_ms must be at most {}", locator::MAX_WAIT_MS);
+        return Ok(response_json(StatusCode::BAD_REQUEST, json!({"msg": msg})));
+    }
+    let found = {
+        let state = state.clone();
+        tokio::task::spawn_blocking(move || state.locator.find(&id))
+            .await
+            .unwrap()
+    };
+    let Some(game) = found else {
+        return Ok(response_json(
+            StatusCode::NOT_FOUND,
+            json!({"msg": "game not installed"}),
+        ));
+    };
+    let wait = request.wait.then(|| Duration::from_millis(timeout_ms));
+    Ok(match locator::launch(&game, &state.games, wait).await {
+        Ok(launched) => {
+            let code = match launched.launched && !request.wait {
+                true => StatusCode::ACCEPTED,
+                false => StatusCode::OK,
+            };
+            response_json(code, json!(launched))
+        },
+        Err(err @ LaunchError::Timeout) => response_json(StatusCode::GATEWAY_TIMEOUT, json!({"msg": err.to_string()})),
+        Err(err) => response_json(StatusCode::INTERNAL_SERVER_ERROR, json!({"msg": err.to_string()})),
+    })
+}
+
+// 诊断报告，只要求令牌有效
+async fn api_system(grant: Grant, State(state): State<Arc<AppState>>) -> Result<Json<Value>, Forbidden> {
+    grant.require(Scope::Windows)?;
+    Ok(Json(doctor::collect(state.config.clone()).await))
+}
+
+// 客户端通过子协议 `jsonrpc-2.0` 或 `?protocol=jsonrpc` 选择 JSON-RPC，否则使用默认格式
+async fn api_ws(
+    Path(token): Path<String>,
+    uri: axum::http::Uri,
+    ws: WebSocketUpgrade,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    if let Ok(token) = Uuid::parse_str(&token) {
+        if let Some(grant) = state.tokens.get(&token) {
+            let requested = ws
+                .requested_protocols()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .any(|protocol| protocol.trim() == rpc::SUBPROTOCOL);
+            let queried = uri
+                .query()
+                .is_some_and(|query| query.split('&').any(|pair| pair == "protocol=jsonrpc"));
+            let wire = if requested || queried {
+                Wire::JsonRpc
+            } else {
+                Wire::Envelope
+            };
+            let max_message_bytes = state.config.ws_max_message_bytes;
+            return ws
+                .protocols([rpc::SUBPROTOCOL])
+                .max_message_size(max_message_bytes)
+                .max_frame_size(max_message_bytes)
+                .on_upgrade(move |socket| handle_ws(socket, state, grant, wire));
+        }
     }
     response_json(StatusCode::UNAUTHORIZED, json!({}))
 }
@@ -262,141 +756,625 @@ async fn api_get_upgrade_yas(State(state): State<Arc<AppState>>) -> Response<Bod
     }
 }
 
-async fn make_internal_request(method: &str, url: &str, body: Value) -> Value {
-    let url = &format!("http://127.0.0.1:32333{url}");
+// 将 WebSocket 中的 `api` 请求交给同一个 `Router` 处理，并带上该连接的令牌与权限
+async fn dispatch_api(state: &AppState, grant: &Grant, request: ApiRequest) -> ApiResponse {
+    let Ok(method) = Method::from_bytes(request.method.to_uppercase().as_bytes()) else {
+        return ApiResponse {
+            status: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+            body: json!({"msg": "unsupported method"}),
+        };
+    };
+    // 莫娜占卜铺将请求体序列化为字符串后发送
+    let body = match request.body {
+        None | Some(Value::Null) => Body::empty(),
+        Some(Value::String(body)) => Body::from(body),
+        Some(body) => Body::from(body.to_string()),
+    };
+    let http_request = Request::builder()
+        .method(method)
+        .uri(&request.url)
+        .header(ACCEPT, "application/json")
+        .header(CONTENT_TYPE, "application/json")
+        .extension(grant.clone())
+        .body(body);
+    let http_request = match http_request {
+        Ok(http_request) => http_request,
+        Err(err) => {
+            return ApiResponse {
+                status: StatusCode::BAD_REQUEST.as_u16(),
+                body: json!({"msg": err.to_string()}),
+            }
+        },
+    };
 
-    let method = {
-        let method = method.to_uppercase().into_bytes();
-        Method::from_bytes(&method).expect("Unsupport HTTP method")
+    let router = state
+        .router
+        .get()
+        .expect("router is initialized before serving")
+        .clone();
+    let response = match router.oneshot(http_request).await {
+        Ok(response) => response,
+        Err(err) => match err {},
     };
+    let status = response.status().as_u16();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    ApiResponse { status, body }
+}
 
-    let response = reqwest::Client::new()
-        .request(method, url)
-        .header("Accept", "application/json")
-        .body(body.to_string())
-        .send()
-        .await;
+async fn api_yas(State(state): State<Arc<AppState>>) -> Json<Value> {
+    Json(state.scans.latest_result().unwrap_or_else(|| json!({})))
+}
 
-    match response {
-        Ok(response) => {
-            let status = response.status().as_u16();
-            let body: Value = response.json().await.unwrap_or_default();
-            json!({ "status": status, "body": body})
-        },
-        Err(err) => {
-            panic!("{}", err)
+async fn api_scans_current(grant: Grant, State(state): State<Arc<AppState>>) -> Result<Json<Value>, Forbidden> {
+    grant.require(Scope::Scan)?;
+    Ok(match state.scans.current() {
+        Some(scan) => {
+            let presence = state.scans.hub().presence(&scan.id);
+            Json(json!({"msg": "running", "scan": scan, "presence": presence}))
         },
-    }
+        None => Json(json!({"msg": "idle"})),
+    })
 }
 
-async fn api_yas() -> Json<Value> {
-    let mona_json_path = current_dir_file("mona.json");
-    match fs::File::open(Path::new(&mona_json_path)) {
-        Ok(mona_json) => Json(serde_json::from_reader(&mona_json).unwrap()),
-        Err(err) => {
-            eprintln!("{err}");
-            Json(json!({}))
-        },
-    }
+async fn api_scans(grant: Grant, State(state): State<Arc<AppState>>) -> Result<Json<Value>, Forbidden> {
+    grant.require(Scope::Scan)?;
+    Ok(Json(json!(state.scans.list())))
 }
 
-async fn handle_ws(socket: WebSocket) {
-    #[derive(Debug, Deserialize)]
-    struct ApiData {
-        url: String,
-        method: String,
-        body: Option<Value>,
-    }
+async fn api_scan(
+    grant: Grant,
+    Path(id): Path<Uuid>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response<Body>, Forbidden> {
+    grant.require(Scope::Scan)?;
+    Ok(match state.scans.get(&id) {
+        Some(scan) => response_json(StatusCode::OK, json!(scan)),
+        None => response_json(StatusCode::NOT_FOUND, json!({"msg": "not found"})),
+    })
+}
 
-    let (mut sender, mut receiver) = socket.split();
-    let (tx, mut rx) = mpsc::channel(1);
+async fn api_scan_result(
+    grant: Grant,
+    Path(id): Path<Uuid>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response<Body>, Forbidden> {
+    grant.require(Scope::Scan)?;
+    Ok(match state.scans.result(&id) {
+        Some(result) => response_json(StatusCode::OK, result),
+        None => response_json(StatusCode::NOT_FOUND, json!({"msg": "not found"})),
+    })
+}
+
+// 一个连接使用的消息格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Wire {
+    // `{action, data, id}`，莫娜占卜铺使用的格式
+    Envelope,
+    JsonRpc,
+}
+
+// 一个 WebSocket 连接的状态
+struct Connection {
+    state: Arc<AppState>,
+    grant: Grant,
+    outbox: Outbox,
+    // 在扫描的广播中心中代表本连接
+    id: Uuid,
+    session: Session,
+    // 重新连接的客户端订阅的实时输出，连接断开时一并结束
+    live_tasks: Vec<JoinHandle<()>>,
+    watching_windows: bool,
+}
+
+// `yas` 没能开始运行的原因
+enum RunError {
+    Busy(Box<ScanInfo>),
+    InvalidArgv(Vec<ArgError>),
+    // 无法创建扫描目录等
+    Failed(String),
+}
+
+async fn handle_ws(socket: WebSocket, state: Arc<AppState>, grant: Grant, wire: Wire) {
+    let config = &state.config;
+    let (sender, mut receiver) = socket.split();
+    let (outbox, rx) = Outbox::new(config.ws_output_queue_lines, config.ws_output_overflow);
+    let ping_interval = Duration::from_secs(config.ws_ping_interval_secs.max(1));
+    let session = match wire {
+        Wire::Envelope => Session::legacy(),
+        Wire::JsonRpc => Session::latest(),
+    };
+    let sender_task = tokio::spawn(send_loop(
+        sender,
+        rx,
+        outbox.output.clone(),
+        ping_interval,
+        wire,
+        session.clone(),
+    ));
 
-    enum Task {
-        Output(String),
-        Other(String),
+    let idle_timeout = Duration::from_secs(config.ws_idle_timeout_secs.max(1));
+    let mut connection = Connection {
+        state: state.clone(),
+        grant,
+        outbox,
+        id: Uuid::new_v4(),
+        session,
+        live_tasks: Vec::new(),
+        watching_windows: false,
+    };
+    connection.watch_windows();
+
+    loop {
+        let next = tokio::select! {
+            next = tokio::time::timeout(idle_timeout, receiver.next()) => next,
+            _ = connection.outbox.closed() => break,
+        };
+        let text = match next {
+            Ok(Some(Ok(Message::Text(text)))) => text,
+            Ok(Some(Ok(Message::Close(_))) | None) => break,
+            // 心跳回应等
+            Ok(Some(Ok(_))) => continue,
+            // 超出大小限制的消息等
+            Ok(Some(Err(err))) => {
+                eprintln!("WebSocket 错误：{err}");
+                break;
+            },
+            Err(_) => {
+                println!("客户端超过 {} 秒无响应，断开连接", idle_timeout.as_secs());
+                break;
+            },
+        };
+        match wire {
+            Wire::Envelope => connection.handle_message(&text).await,
+            Wire::JsonRpc => connection.handle_rpc(&text).await,
+        }
+    }
+    for task in connection.live_tasks.drain(..) {
+        task.abort();
     }
+    drop(connection);
+    let _ = sender_task.await;
+}
 
-    let sender_task = tokio::spawn(async move {
-        while let Some(line) = rx.recv().await {
-            match line {
-                Task::Output(line) => {
-                    println!("{line}");
-                    let json = serde_json::to_string(&json!({"action": "yas-output", "data": line})).unwrap();
-                    sender.send(Message::Text(json.into())).await.unwrap();
-                },
-                Task::Other(json) => {
-                    // let json = serde_json::to_string(&json).unwrap();
-                    sender.send(Message::Text(json.into())).await.unwrap();
+impl Connection {
+    async fn handle_message(&mut self, text: &str) {
+        let message = match parse_client_message(text) {
+            Ok(message) => message,
+            Err(error) => {
+                self.outbox.message(error).await;
+                return;
+            },
+        };
+        let id = message.id;
+        match message.action {
+            ClientAction::Hello(hello) => match negotiate(&hello, id) {
+                Ok((negotiated, response)) => {
+                    self.session = negotiated.clone();
+                    self.outbox.send(Task::Negotiated(negotiated)).await;
+                    self.outbox.message(response).await;
+                    self.watch_windows();
                 },
-            }
+                Err(error) => self.outbox.message(error).await,
+            },
+            ClientAction::Attach(_) if !self.session.has(Capability::Attach) => {
+                let error = ServerMessage::error(id, ErrorCode::NotSupported, "`attach` was not negotiated");
+                self.outbox.message(error).await;
+            },
+            ClientAction::Attach(request)
+                if request.role == Role::Controller && !self.session.has(Capability::Control) =>
+            {
+                let error = ServerMessage::error(id, ErrorCode::NotSupported, "`control` was not negotiated");
+                self.outbox.message(error).await;
+            },
+            ClientAction::Attach(request) => {
+                if let Some(task) = attach_scan(&self.state, &self.outbox, self.id, request, id).await {
+                    self.live_tasks.push(task);
+                }
+            },
+            ClientAction::Cancel(request) => {
+                let status = match self.state.scans.hub().cancel(&request.id, self.id) {
+                    Ok(()) => 200,
+                    Err(HubError::NotController) => 403,
+                    Err(_) => 404,
+                };
+                self.outbox
+                    .message(ServerMessage::Cancel {
+                        data: CancelResponse { status },
+                        id,
+                    })
+                    .await;
+            },
+            ClientAction::Api(request) if request.url != "/api/yas" => {
+                let response = dispatch_api(&self.state, &self.grant, request).await;
+                self.outbox.message(ServerMessage::Api { data: response, id }).await;
+            },
+            ClientAction::Api(request) => {
+                // 与 HTTP 接口及 JSON-RPC 的 `yas.run` 相同，运行 yas 需要 `scan` 权限
+                if let Err(Forbidden(scope)) = self.grant.require(Scope::Scan) {
+                    let response = ServerMessage::api(id, 403, json!({"msg": "forbidden", "scope": scope}));
+                    self.outbox.message(response).await;
+                    return;
+                }
+                let Some(argv) = parse_yas_argv(request.body) else {
+                    let error = ServerMessage::error(id, ErrorCode::BadRequest, "missing `argv` in request body");
+                    self.outbox.message(error).await;
+                    return;
+                };
+                match self.start_yas(argv).await {
+                    Ok(_) => {},
+                    Err(RunError::Busy(scan)) => self.outbox.message(ServerMessage::busy(id, &scan)).await,
+                    Err(RunError::InvalidArgv(errors)) => {
+                        self.outbox.message(ServerMessage::invalid_argv(id, &errors)).await
+                    },
+                    Err(RunError::Failed(msg)) => {
+                        let response = ServerMessage::api(id, 500, json!({"msg": msg}));
+                        self.outbox.message(response).await
+                    },
+                }
+            },
         }
-    });
+    }
 
-    while let Some(Ok(Message::Text(payload))) = receiver.next().await {
-        let payload: Value = serde_json::from_str(&payload).unwrap();
-        if let Some("api") = payload["action"].as_str() {
-            let data: ApiData = serde_json::from_value(payload["data"].clone()).unwrap();
-            if data.url != "/api/yas" {
-                let response: Value = json!({
-                   "action": "api",
-                   "data": make_internal_request(&data.method, &data.url, data.body.unwrap_or_default()).await,
-                   "id": payload["id"],
-                });
-                tx.send(Task::Other(response.to_string())).await.unwrap();
-            } else {
-                let argv = {
-                    let body = data.body.unwrap();
-                    let body: Value = serde_json::from_str(body.as_str().unwrap()).unwrap();
-                    body["argv"].as_str().unwrap().to_owned()
+    async fn handle_rpc(&mut self, text: &str) {
+        let response = match rpc::parse(text) {
+            Incoming::Invalid(response) => Some(response),
+            Incoming::Single(request) => self.respond(request).await,
+            Incoming::Batch(requests) => {
+                let mut responses = Vec::new();
+                for request in requests {
+                    responses.push(self.respond(request).await);
+                }
+                rpc::batch(responses)
+            },
+        };
+        if let Some(response) = response {
+            self.outbox.send(Task::Reply(response)).await;
+        }
+    }
+
+    async fn respond(&mut self, request: Result<rpc::Request, Value>) -> Option<Value> {
+        let request = match request {
+            Ok(request) => request,
+            Err(response) => return Some(response),
+        };
+        let id = request.id.clone();
+        rpc::reply(id, self.call(request).await)
+    }
+
+    async fn call(&mut self, request: rpc::Request) -> Result<Value, rpc::Error> {
+        let params = &request.params;
+        match request.method.as_str() {
+            "windows.list" => self.call_api(Method::GET, "/api/windows".to_owned()).await,
+            "windows.focus" => {
+                let hwnd = rpc::param(params, "hwnd", 0)
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| rpc::Error::new(rpc::INVALID_PARAMS, "`hwnd` must be a window handle"))?;
+                self.call_api(Method::PATCH, format!("/api/windows/{hwnd}")).await
+            },
+            "yas.run" => {
+                self.require(Scope::Scan)?;
+                let argv = rpc::param(params, "argv", 0)
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| rpc::Error::new(rpc::INVALID_PARAMS, "`argv` must be a string"))?;
+                match self.start_yas(argv.to_owned()).await {
+                    Ok(scan) => Ok(json!({"scan": scan})),
+                    Err(RunError::Busy(scan)) => Err(rpc::Error::new(rpc::BUSY, "busy").with_data(json!(scan))),
+                    Err(RunError::InvalidArgv(errors)) => {
+                        Err(rpc::Error::new(rpc::INVALID_PARAMS, "invalid argv").with_data(json!(errors)))
+                    },
+                    Err(RunError::Failed(msg)) => Err(rpc::Error::new(rpc::SERVER_ERROR, msg)),
+                }
+            },
+            "yas.cancel" => {
+                self.require(Scope::Scan)?;
+                // 缺省时取消正在运行的扫描
+                let scan = match rpc::param(params, "id", 0) {
+                    Some(id) => serde_json::from_value(id.clone())
+                        .map_err(|_| rpc::Error::new(rpc::INVALID_PARAMS, "`id` must be a scan id"))?,
+                    None => match self.state.scans.current() {
+                        Some(scan) => scan.id,
+                        None => return Err(rpc::Error::new(rpc::NOT_FOUND, "no scan is running")),
+                    },
                 };
-                let command = current_dir_file("yas_artifact.exe");
-                println!("运行 {} {}", command.display(), argv);
-                let mut child = std::process::Command::new(&command)
-                    .args(argv.split_whitespace())
-                    .stdin(std::process::Stdio::inherit())
-                    .stdout(std::process::Stdio::piped())
-                    .stderr(std::process::Stdio::piped())
-                    .spawn()
-                    .unwrap();
-
-                let stdout = child.stdout.take().unwrap();
-                let tx2 = tx.clone();
-                let task1 = tokio::spawn(async move {
-                    let reader = BufReader::new(stdout);
-                    for line in reader.lines() {
-                        let line = line.unwrap();
-                        tx2.send(Task::Output(line)).await.unwrap();
-                    }
-                });
-
-                let stderr = child.stderr.take().unwrap();
-                let tx2 = tx.clone();
-                let task2 = tokio::spawn(async move {
-                    let reader = BufReader::new(stderr);
-                    for line in reader.lines() {
-                        let line = line.unwrap();
-                        tx2.send(Task::Output(line)).await.unwrap();
+                match self.state.scans.hub().cancel(&scan, self.id) {
+                    Ok(()) => Ok(json!({"scan": scan})),
+                    Err(HubError::NotController) => Err(rpc::Error::new(rpc::FORBIDDEN, "not the controller")),
+                    Err(_) => Err(rpc::Error::new(rpc::NOT_FOUND, "the scan is not running")),
+                }
+            },
+            "yas.update" => self.call_api(Method::POST, "/api/upgrade/yas".to_owned()).await,
+            "scan.result" => match rpc::param(params, "id", 0).and_then(Value::as_str) {
+                Some(id) => self.call_api(Method::GET, format!("/api/scans/{id}/result")).await,
+                None => self.call_api(Method::GET, "/api/yas".to_owned()).await,
+            },
+            method => Err(rpc::Error::new(
+                rpc::METHOD_NOT_FOUND,
+                format!("method `{method}` not found"),
+            )),
+        }
+    }
+
+    fn require(&self, scope: Scope) -> Result<(), rpc::Error> {
+        self.grant
+            .require(scope)
+            .map_err(|_| rpc::Error::new(rpc::FORBIDDEN, "forbidden").with_data(json!({"scope": scope})))
+    }
+
+    // 与 `api` 消息一样交给 `Router` 处理，非 2xx 的响应转换为错误
+    async fn call_api(&self, method: Method, url: String) -> Result<Value, rpc::Error> {
+        let request = ApiRequest {
+            url,
+            method: method.to_string(),
+            body: None,
+        };
+        let response = dispatch_api(&self.state, &self.grant, request).await;
+        let code = match response.status {
+            200..=299 => return Ok(response.body),
+            401 | 403 => rpc::FORBIDDEN,
+            404 => rpc::NOT_FOUND,
+            _ => rpc::SERVER_ERROR,
+        };
+        let message = format!("HTTP {}", response.status);
+        Err(rpc::Error::new(code, message).with_data(json!({"status": response.status, "body": response.body})))
+    }
+
+    // 协商了 `window-events` 且有窗口权限的连接接收游戏窗口事件，先补发已经打开的窗口
+    fn watch_windows(&mut self) {
+        if self.watching_windows || !self.session.has(Capability::WindowEvents) || !self.grant.has(Scope::Windows) {
+            return;
+        }
+        self.watching_windows = true;
+        let (windows, live) = self.state.games.subscribe();
+        self.live_tasks
+            .push(tokio::spawn(forward_windows(self.outbox.clone(), windows, live)));
+    }
+
+    // 在后台运行 yas，返回扫描 id。扫描不随本连接断开而结束
+    async fn start_yas(&self, argv: String) -> Result<Uuid, RunError> {
+        let (started_tx, started_rx) = oneshot::channel();
+        tokio::spawn(run_yas(
+            self.state.clone(),
+            self.outbox.clone(),
+            self.id,
+            argv,
+            started_tx,
+        ));
+        started_rx.await.expect("run_yas reports whether the scan started")
+    }
+}
+
+async fn forward_windows(outbox: Outbox, windows: Vec<GameWindow>, mut live: broadcast::Receiver<GameEvent>) {
+    for window in windows {
+        outbox.message(GameEvent::Appeared(window).message()).await;
+    }
+    loop {
+        let event = match live.recv().await {
+            Ok(event) => event,
+            // 窗口事件很少，落后时跳过即可，`GET /api/game` 可以取得当前状态
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        };
+        if outbox.is_closed() {
+            break;
+        }
+        outbox.message(event.message()).await;
+    }
+}
+
+// 按顺序发送控制消息与积压的 yas 输出，并定时发送心跳。发送失败时结束
+async fn send_loop(
+    mut sender: SplitSink<WebSocket, Message>,
+    mut rx: mpsc::Receiver<Task>,
+    output: Arc<OutputQueue>,
+    ping_interval: Duration,
+    wire: Wire,
+    mut session: Session,
+) {
+    let mut ping = tokio::time::interval_at(Instant::now() + ping_interval, ping_interval);
+    ping.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let message = tokio::select! {
+            // 控制消息优先，保证补发的输出先于实时输出
+            biased;
+            task = rx.recv() => match task {
+                Some(Task::Message(message)) => message,
+                Some(Task::Line(line)) => ServerMessage::output(line, &session),
+                Some(Task::Note(text)) => {
+                    println!("{text}");
+                    ServerMessage::note(text)
+                },
+                Some(Task::Negotiated(negotiated)) => {
+                    session = negotiated;
+                    continue;
+                },
+                Some(Task::Reply(reply)) => {
+                    if sender.send(Message::Text(reply.to_string().into())).await.is_err() {
+                        break;
                     }
-                });
+                    continue;
+                },
+                None => break,
+            },
+            _ = output.ready() => match output.take_dropped() {
+                Some((dropped, total)) => ServerMessage::output_dropped(dropped, total, &session),
+                None => match output.pop() {
+                    Some(line) => ServerMessage::output(line, &session),
+                    None => continue,
+                },
+            },
+            _ = ping.tick() => {
+                if sender.send(Message::Ping(Bytes::new())).await.is_err() {
+                    break;
+                }
+                continue;
+            },
+        };
+        let json = match wire {
+            Wire::Envelope => serde_json::to_string(&message).unwrap(),
+            Wire::JsonRpc => rpc::notification(&message).to_string(),
+        };
+        if sender.send(Message::Text(json.into())).await.is_err() {
+            break;
+        }
+    }
+}
 
-                tx.send(Task::Output(format!("{} {}", command.display(), argv)))
-                    .await
-                    .unwrap();
-                tx.send(Task::Other(json!({"action": "yas","data": "load"}).to_string()))
-                    .await
-                    .unwrap();
+// 补发错过的输出，扫描仍在运行时返回转发后续输出的任务
+async fn attach_scan(
+    state: &AppState,
+    outbox: &Outbox,
+    connection: Uuid,
+    request: AttachRequest,
+    id: Value,
+) -> Option<JoinHandle<()>> {
+    let mut subscription = match state
+        .scans
+        .hub()
+        .subscribe(&request.id, request.since, connection, request.role)
+    {
+        Ok(subscription) => subscription,
+        Err(err) => {
+            let status = match err {
+                HubError::ControllerTaken => 409,
+                _ => 404,
+            };
+            let response = ServerMessage::Attach {
+                data: AttachResponse { status, role: None },
+                id,
+            };
+            outbox.message(response).await;
+            return None;
+        },
+    };
+    let response = ServerMessage::Attach {
+        data: AttachResponse {
+            status: 200,
+            role: Some(subscription.role),
+        },
+        id,
+    };
+    outbox.message(response).await;
+    for line in std::mem::take(&mut subscription.lines) {
+        outbox.send(Task::Line(line)).await;
+    }
 
-                tx.send(Task::Output("⚠️如果莫名其妙卡在此处请输入回车喵~".to_owned()))
-                    .await
-                    .unwrap();
+    let exit = ServerMessage::yas(YasEvent::Exit, Some(request.id));
+    if subscription.live.is_none() {
+        outbox.message(exit).await;
+        return None;
+    }
+    Some(forward_live(outbox.clone(), subscription, Some(exit)))
+}
 
-                tokio::try_join!(task1, task2).unwrap();
-                println!("结束 {} {}", command.display(), argv);
+// 将订阅的实时输出转发到连接，直到扫描结束或连接断开。任务结束时退出订阅
+fn forward_live(outbox: Outbox, mut subscription: Subscription, exit: Option<ServerMessage>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let Some(mut live) = subscription.live.take() else {
+            return;
+        };
+        loop {
+            let received = tokio::select! {
+                received = live.recv() => received,
+                _ = outbox.closed() => return,
+            };
+            match received {
+                Ok(line) => outbox.line(line),
+                Err(RecvError::Lagged(n)) => outbox.output.record_dropped(n),
+                Err(RecvError::Closed) => break,
             }
         }
+        drop(subscription);
+        if let Some(exit) = exit {
+            outbox.message(exit).await;
+        }
+    })
+}
+
+// `/api/yas` 的请求体为 JSON 字符串（莫娜占卜铺）或对象，其中 `argv` 为 yas 的参数
+fn parse_yas_argv(body: Option<Value>) -> Option<String> {
+    let body = match body? {
+        Value::String(body) => serde_json::from_str(&body).ok()?,
+        body => body,
+    };
+    body.get("argv")?.as_str().map(str::to_owned)
+}
+
+// 校验参数并运行 yas，`started` 收到扫描 id 或没能开始的原因
+async fn run_yas(
+    state: Arc<AppState>,
+    outbox: Outbox,
+    connection: Uuid,
+    argv: String,
+    started: oneshot::Sender<Result<Uuid, RunError>>,
+) {
+    let command = match &state.replay {
+        Some(replay) => replay.file.clone(),
+        None => current_dir_file("yas_artifact.exe"),
+    };
+    let slot = match state.scans.begin(&command) {
+        Ok(slot) => slot,
+        Err(ScanError::Busy(scan)) => {
+            let _ = started.send(Err(RunError::Busy(scan)));
+            return;
+        },
+        Err(err) => {
+            eprintln!("{err}");
+            let _ = started.send(Err(RunError::Failed(err.to_string())));
+            return;
+        },
+    };
+
+    let yas_version = YasReleaseInfo::read_from_file().unwrap_or_default().version;
+    let policy = YasArgsPolicy::load(&yas_version);
+    let args = match split_argv(&argv)
+        .map_err(|err| vec![err])
+        .and_then(|args| policy.validate(&args, &slot.dir()))
+    {
+        Ok(args) => args,
+        Err(errors) => {
+            let _ = started.send(Err(RunError::InvalidArgv(errors)));
+            return;
+        },
+    };
+    let argv = args.join(" ");
+    println!("运行 {} {}（扫描 {}）", command.display(), argv, slot.id());
+
+    // 发起扫描的连接即为控制者，和其他订阅者一样从广播中心接收输出
+    let subscription = state
+        .scans
+        .hub()
+        .subscribe(&slot.id(), 0, connection, Role::Controller)
+        .expect("the scan was just opened");
+    let forward_task = forward_live(outbox.clone(), subscription, None);
+    let _ = started.send(Ok(slot.id()));
+
+    outbox.send(Task::Note(format!("{} {}", command.display(), argv))).await;
+    // `scan` 为本次扫描的 id，断线后可凭此通过 `attach` 重新连接
+    outbox
+        .message(ServerMessage::yas(YasEvent::Load, Some(slot.id())))
+        .await;
+    outbox
+        .send(Task::Note("⚠️如果莫名其妙卡在此处请输入回车喵~".to_owned()))
+        .await;
+
+    let result = match &state.replay {
+        Some(replay) => slot.replay(&replay.file, replay.speed, &args).await,
+        None => slot.run(&args).await,
+    };
+    match result {
+        Ok(scan) => println!("yas 退出：{:?}", scan.exit_code),
+        Err(err) => {
+            eprintln!("{err}");
+            outbox.send(Task::Note(err.to_string())).await;
+        },
     }
-    drop(tx);
-    tokio::try_join!(sender_task).unwrap();
+    let _ = forward_task.await;
+    println!("结束 {} {}", command.display(), argv);
 }
 
 fn response_json(code: StatusCode, body: Value) -> Response<Body> {
@@ -407,14 +1385,41 @@ fn response_json(code: StatusCode, body: Value) -> Response<Body> {
         .expect("Failed to build response")
 }
 
-pub async fn start_server(bind_addr: &str) {
+pub async fn start_server(bind_addr: &str, replay: Option<Replay>) {
     println!("Server running on http://{bind_addr}");
     enable_virtual_terminal_sequences().unwrap();
 
+    let app = build_app(Config::load(), replay, console_consent);
+
+    #[cfg(feature = "tracing")]
+    {
+        tracing_subscriber::fmt().with_max_level(tracing::Level::DEBUG).init();
+        let app = app.layer(tower_http::trace::TraceLayer::new_for_http());
+    }
+
+    let listener = tokio::net::TcpListener::bind(bind_addr).await.unwrap();
+    axum::serve(listener, app).await.unwrap();
+}
+
+// 测试中以 `consent` 代替控制台确认，在进程内构造同样的 `Router`
+pub(crate) fn build_app(config: Config, replay: Option<Replay>, consent: fn(&str, &[Scope]) -> bool) -> Router {
+    let events = Arc::new(EventBus::new(config.event_history));
+    let windows = window_manager();
     let shared_state = Arc::new(AppState {
-        authorized_tokens: Mutex::new(HashSet::new()),
+        scans: ScanManager::new(&config, events.clone()),
+        games: Arc::new(GameWatcher::new(&config, windows.clone())),
+        locator: GameLocator::new(registry()),
+        windows,
+        capture: platform_capture(),
+        input: Arc::new(InputController::new(&config, input_injector())),
+        config,
+        tokens: TokenStore::default(),
+        events,
         yas_update_state: Mutex::new(YasUpdateState::NoUpdate),
         yas_download_state: Mutex::new((0, 114514)),
+        replay,
+        consent,
+        router: OnceLock::new(),
     });
 
     let cors = CorsLayer::new()
@@ -427,19 +1432,29 @@ pub async fn start_server(bind_addr: &str) {
         .route("/token", post(api_token))
         .route("/", options(|| async { "" }))
         .route("/api/windows", get(api_api_windows))
-        .route("/api/windows/:hwnd", patch(api_patch_windows))
-        .route("/ws/:uuid", get(api_ws))
+        .route("/api/windows/{hwnd}", patch(api_patch_windows))
+        .route("/api/windows/{hwnd}/capture", get(api_window_capture))
+        .route("/api/windows/{hwnd}/capture/stream", get(api_window_capture_stream))
+        .route("/api/windows/{hwnd}/input", post(api_window_input))
+        .route("/api/input", get(api_input))
+        .route("/api/displays", get(api_displays))
+        .route("/ws/{uuid}", get(api_ws))
+        .route("/api/events", get(api_events))
+        .route("/api/system", get(api_system))
+        .route("/api/game", get(api_game))
+        .route("/api/games", get(api_games))
+        .route("/api/games/{id}/launch", post(api_launch_game))
         .route("/api/upgrade/yas", post(api_post_upgrade_yas).get(api_get_upgrade_yas))
         .route("/api/yas", get(api_yas))
+        .route("/api/scans", get(api_scans))
+        .route("/api/scans/current", get(api_scans_current))
+        .route("/api/scans/{id}", get(api_scan))
+        .route("/api/scans/{id}/result", get(api_scan_result))
         .layer(cors)
-        .with_state(shared_state);
-
-    #[cfg(feature = "tracing")]
-    {
-        tracing_subscriber::fmt().with_max_level(tracing::Level::DEBUG).init();
-        let app = app.layer(tower_http::trace::TraceLayer::new_for_http());
-    }
-
-    let listener = tokio::net::TcpListener::bind(bind_addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+        .with_state(shared_state.clone());
+    let _ = shared_state.router.set(app.clone());
+    let interval = Duration::from_millis(shared_state.config.game_poll_interval_ms.max(100));
+    tokio::spawn(shared_state.games.clone().run(interval, shared_state.events.clone()));
+    tokio::spawn(shared_state.input.clone().watch_hotkey());
+    app
 }
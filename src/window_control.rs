@@ -0,0 +1,236 @@
+// `PATCH /api/windows/{hwnd}` 的窗口操作。请求体为 `{"actions": [...]}`，按顺序执行，
+// 某一步失败后其余操作不再执行。没有请求体时与原版一样只切换到前台。
+
+use crate::platform::{DisplayInfo, WindowManager};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum WindowAction {
+    Focus,
+    Restore,
+    Minimize,
+    BringToTop,
+    // 移动到指定显示器（设备名或 `primary`）的 `(x, y)` 处，未指定显示器时为屏幕坐标
+    Move {
+        #[serde(default)]
+        x: i32,
+        #[serde(default)]
+        y: i32,
+        display: Option<String>,
+    },
+    // 调整窗口大小，使客户区即游戏画面为该分辨率
+    Resize {
+        width: i32,
+        height: i32,
+    },
+}
+
+impl WindowAction {
+    fn name(&self) -> &'static str {
+        match self {
+            WindowAction::Focus => "focus",
+            WindowAction::Restore => "restore",
+            WindowAction::Minimize => "minimize",
+            WindowAction::BringToTop => "bring-to-top",
+            WindowAction::Move { .. } => "move",
+            WindowAction::Resize { .. } => "resize",
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct WindowRequest {
+    #[serde(default)]
+    pub actions: Vec<WindowAction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum ActionStatus {
+    Done,
+    Failed { error: String },
+    // 之前的操作失败而没有执行
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ActionResult {
+    pub action: &'static str,
+    #[serde(flatten)]
+    pub status: ActionStatus,
+}
+
+impl WindowRequest {
+    pub fn actions(self) -> Vec<WindowAction> {
+        match self.actions.is_empty() {
+            true => vec![WindowAction::Focus],
+            false => self.actions,
+        }
+    }
+}
+
+pub fn apply(windows: &dyn WindowManager, hwnd: usize, actions: &[WindowAction]) -> Vec<ActionResult> {
+    let mut failed = false;
+    actions
+        .iter()
+        .map(|action| {
+            let status = if failed {
+                ActionStatus::Skipped
+            } else {
+                match run(windows, hwnd, action) {
+                    Ok(()) => ActionStatus::Done,
+                    Err(error) => {
+                        failed = true;
+                        ActionStatus::Failed { error }
+                    },
+                }
+            };
+            ActionResult {
+                action: action.name(),
+                status,
+            }
+        })
+        .collect()
+}
+
+fn run(windows: &dyn WindowManager, hwnd: usize, action: &WindowAction) -> Result<(), String> {
+    let result = match action {
+        WindowAction::Focus => windows.focus(hwnd),
+        WindowAction::Restore => windows.restore(hwnd),
+        WindowAction::Minimize => windows.minimize(hwnd),
+        WindowAction::BringToTop => windows.bring_to_top(hwnd),
+        WindowAction::Move { x, y, display } => {
+            let (left, top) = match display {
+                Some(name) => {
+                    let displays = windows.displays().map_err(|err| err.to_string())?;
+                    let display = find_display(&displays, name).ok_or_else(|| format!("no display `{name}`"))?;
+                    (display.x, display.y)
+                },
+                None => (0, 0),
+            };
+            windows.move_to(hwnd, left + x, top + y)
+        },
+        WindowAction::Resize { width, height } if *width <= 0 || *height <= 0 => {
+            return Err(format!("invalid size {width}×{height}"));
+        },
+        WindowAction::Resize { width, height } => windows.resize_client(hwnd, *width, *height),
+    };
+    result.map_err(|err| err.to_string())
+}
+
+fn find_display<'a>(displays: &'a [DisplayInfo], name: &str) -> Option<&'a DisplayInfo> {
+    match name {
+        "primary" => displays.iter().find(|display| display.primary),
+        name => displays.iter().find(|display| display.name == name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{FakeWindowManager, Rect, Size, WindowInfo};
+
+    fn game() -> WindowInfo {
+        WindowInfo {
+            class_name: "UnityWndClass".to_owned(),
+            title: "原神".to_owned(),
+            hwnd: 1,
+            height: 759,
+            width: 1296,
+            x: 2000,
+            y: 100,
+            pid: 1,
+            process: "YuanShen.exe".to_owned(),
+            visible: true,
+            minimized: true,
+            maximized: false,
+            monitor: "second".to_owned(),
+            client: Rect {
+                x: 2008,
+                y: 131,
+                width: 1280,
+                height: 720,
+            },
+            dpi: 96,
+            scale: 1.0,
+            logical_client: Size {
+                width: 1280,
+                height: 720,
+            },
+        }
+    }
+
+    fn display(name: &str, x: i32, primary: bool) -> DisplayInfo {
+        DisplayInfo {
+            name: name.to_owned(),
+            primary,
+            x,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            work_area: Rect {
+                x,
+                y: 0,
+                width: 1920,
+                height: 1040,
+            },
+            dpi: 96,
+            scale: 1.0,
+        }
+    }
+
+    fn manager() -> FakeWindowManager {
+        FakeWindowManager::new(
+            vec![game()],
+            vec![display("main", 0, true), display("second", 1920, false)],
+        )
+    }
+
+    fn parse(json: &str) -> Vec<WindowAction> {
+        serde_json::from_str::<WindowRequest>(json).unwrap().actions()
+    }
+
+    #[test]
+    fn empty_body_focuses() {
+        assert!(matches!(parse("{}")[..], [WindowAction::Focus]));
+    }
+
+    #[test]
+    fn restore_move_and_resize() {
+        let windows = manager();
+        let actions = parse(
+            r#"{"actions": [
+                {"type": "restore"},
+                {"type": "move", "display": "primary"},
+                {"type": "resize", "width": 1920, "height": 1080}
+            ]}"#,
+        );
+        let results = apply(&windows, 1, &actions);
+        assert!(results.iter().all(|result| result.status == ActionStatus::Done));
+        let window = windows.list().unwrap().remove(0);
+        assert!(!window.minimized);
+        assert_eq!((window.x, window.y), (0, 0));
+        assert_eq!((window.client.width, window.client.height), (1920, 1080));
+        assert_eq!((window.width, window.height), (1936, 1119));
+    }
+
+    #[test]
+    fn failure_skips_remaining_actions() {
+        let windows = manager();
+        let actions = parse(
+            r#"{"actions": [
+                {"type": "move", "display": "missing"},
+                {"type": "focus"}
+            ]}"#,
+        );
+        let results = apply(&windows, 1, &actions);
+        assert!(matches!(results[0].status, ActionStatus::Failed { .. }));
+        assert_eq!(results[1].status, ActionStatus::Skipped);
+
+        let results = apply(&windows, 2, &[WindowAction::Minimize]);
+        assert!(matches!(results[0].status, ActionStatus::Failed { .. }));
+    }
+}
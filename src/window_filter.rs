@@ -0,0 +1,46 @@
+// 窗口列表的过滤：请求中的查询条件，以及配置中的允许列表。
+// 网页端只需要找到游戏的窗口，默认只公开已知的游戏客户端，避免把所有窗口的标题泄露给网页。
+
+use crate::platform::WindowInfo;
+
+use serde::{Deserialize, Serialize};
+
+// 一条匹配规则，列出的条件需全部满足，没有条件的规则匹配所有窗口
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WindowRule {
+    // 标题包含该字符串，不区分大小写
+    pub title: Option<String>,
+    // 窗口类名完全相同
+    pub class: Option<String>,
+    // 可执行文件名相同，不区分大小写
+    pub process: Option<String>,
+}
+
+impl WindowRule {
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        let title = self
+            .title
+            .as_ref()
+            .is_none_or(|title| window.title.to_lowercase().contains(&title.to_lowercase()));
+        let class = self.class.as_ref().is_none_or(|class| window.class_name == *class);
+        let process = self
+            .process
+            .as_ref()
+            .is_none_or(|process| window.process.eq_ignore_ascii_case(process));
+        title && class && process
+    }
+}
+
+// 窗口是否匹配任意一条规则
+pub fn allowed(rules: &[WindowRule], window: &WindowInfo) -> bool {
+    rules.iter().any(|rule| rule.matches(window))
+}
+
+// `GET /api/windows` 的查询参数中规则以外的部分
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ListOptions {
+    // 只返回可见的窗口
+    pub visible_only: bool,
+}
@@ -0,0 +1,470 @@
+use crate::utils::current_dir_file;
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArgError {
+    pub arg: String,
+    pub reason: String,
+}
+
+impl ArgError {
+    fn new(arg: &str, reason: impl Into<String>) -> ArgError {
+        ArgError {
+            arg: arg.to_owned(),
+            reason: reason.into(),
+        }
+    }
+}
+
+// 按照命令行的习惯切分参数：空白分隔，单双引号包裹的部分视为一个整体。
+// 反斜杠不作为转义符，这样 Windows 路径可以原样传入。
+pub fn split_argv(argv: &str) -> Result<Vec<String>, ArgError> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+
+    for c in argv.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_arg = true;
+            },
+            None if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            },
+            None => {
+                current.push(c);
+                in_arg = true;
+            },
+        }
+    }
+
+    if let Some(q) = quote {
+        return Err(ArgError::new(&current, format!("unterminated quote {q}")));
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum FlagRule {
+    // 不带值的开关
+    Switch,
+    Int { min: i64, max: i64 },
+    Float { min: f64, max: f64 },
+    Choice { values: Vec<String> },
+    Text { max_len: usize },
+    // 只允许指向数据目录之内的路径
+    OutputDir,
+}
+
+impl FlagRule {
+    fn check(&self, flag: &str, value: &str, output_root: &Path) -> Result<String, ArgError> {
+        match self {
+            FlagRule::Switch => Ok(value.to_owned()),
+            FlagRule::Int { min, max } => match value.parse::<i64>() {
+                Ok(n) if (*min..=*max).contains(&n) => Ok(value.to_owned()),
+                _ => Err(ArgError::new(
+                    flag,
+                    format!("expected an integer in {min}..={max}, got `{value}`"),
+                )),
+            },
+            FlagRule::Float { min, max } => match value.parse::<f64>() {
+                Ok(n) if (*min..=*max).contains(&n) => Ok(value.to_owned()),
+                _ => Err(ArgError::new(
+                    flag,
+                    format!("expected a number in {min}..={max}, got `{value}`"),
+                )),
+            },
+            FlagRule::Choice { values } => {
+                if values.iter().any(|v| v == value) {
+                    Ok(value.to_owned())
+                } else {
+                    Err(ArgError::new(
+                        flag,
+                        format!("expected one of {values:?}, got `{value}`"),
+                    ))
+                }
+            },
+            FlagRule::Text { max_len } => {
+                if value.chars().count() <= *max_len {
+                    Ok(value.to_owned())
+                } else {
+                    Err(ArgError::new(
+                        flag,
+                        format!("value is longer than {max_len} characters"),
+                    ))
+                }
+            },
+            FlagRule::OutputDir => match resolve_inside(output_root, value) {
+                Some(path) => Ok(path.display().to_string()),
+                None => Err(ArgError::new(
+                    flag,
+                    format!("`{value}` is outside {}", output_root.display()),
+                )),
+            },
+        }
+    }
+}
+
+// 仅做字面上的规范化，不访问文件系统（目标目录可能还不存在）
+fn resolve_inside(root: &Path, value: &str) -> Option<PathBuf> {
+    let path = Path::new(value);
+    let joined = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        root.join(path)
+    };
+    let mut normalized = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            },
+            Component::CurDir => {},
+            other => normalized.push(other),
+        }
+    }
+    normalized.starts_with(root).then_some(normalized)
+}
+
+// yas 参数白名单，键为 `--flag`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct YasArgsPolicy {
+    pub flags: HashMap<String, FlagRule>,
+}
+
+impl Default for YasArgsPolicy {
+    fn default() -> Self {
+        let int = |min, max| FlagRule::Int { min, max };
+        let flags = [
+            ("--min-star", int(1, 5)),
+            ("--min-level", int(0, 20)),
+            ("--max-row", int(1, 10000)),
+            ("--number", int(0, 5000)),
+            ("--scroll-stop", int(0, 10000)),
+            ("--max-wait-switch-artifact", int(0, 10000)),
+            ("--max-wait-scroll", int(0, 10000)),
+            ("--offset-x", int(-10000, 10000)),
+            ("--offset-y", int(-10000, 10000)),
+            ("--speed", int(1, 5)),
+            ("--window", FlagRule::Text { max_len: 64 }),
+            (
+                "--output-format",
+                FlagRule::Choice {
+                    values: vec!["mona".to_owned(), "good".to_owned()],
+                },
+            ),
+            ("--output-dir", FlagRule::OutputDir),
+            ("--capture-only", FlagRule::Switch),
+            ("--dump", FlagRule::Switch),
+            ("--no-check", FlagRule::Switch),
+            ("--verbose", FlagRule::Switch),
+            ("--mona", FlagRule::Switch),
+            ("--good", FlagRule::Switch),
+        ];
+        YasArgsPolicy {
+            flags: flags.into_iter().map(|(k, v)| (k.to_owned(), v)).collect(),
+        }
+    }
+}
+
+impl YasArgsPolicy {
+    // 从 `yas_args.json` 读取与 yas 版本对应的白名单，格式为 `{ "<version>" | "*": { "--flag": rule } }`。
+    // 文件不存在或没有匹配的版本时使用内置白名单。
+    pub fn load(yas_version: &str) -> YasArgsPolicy {
+        YasArgsPolicy::load_from(&current_dir_file("yas_args.json"), yas_version)
+    }
+
+    fn load_from(path: &Path, yas_version: &str) -> YasArgsPolicy {
+        let Ok(file) = fs::File::open(path) else {
+            return YasArgsPolicy::default();
+        };
+        match serde_json::from_reader::<_, HashMap<String, HashMap<String, FlagRule>>>(file) {
+            Ok(mut profiles) => match profiles.remove(yas_version).or_else(|| profiles.remove("*")) {
+                Some(flags) => YasArgsPolicy { flags },
+                None => YasArgsPolicy::default(),
+            },
+            Err(err) => {
+                eprintln!("无法解析 {}：{err}，使用内置参数白名单", path.display());
+                YasArgsPolicy::default()
+            },
+        }
+    }
+
+    // 校验参数并返回实际传给 yas 的参数，任何一个参数不合法都会整体拒绝
+    pub fn validate(&self, args: &[String], output_root: &Path) -> Result<Vec<String>, Vec<ArgError>> {
+        let mut accepted = Vec::new();
+        let mut errors = Vec::new();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value)),
+                _ => (arg.as_str(), None),
+            };
+            let Some(rule) = self.flags.get(flag) else {
+                let reason = if flag.starts_with('-') {
+                    "flag is not allowed"
+                } else {
+                    "unexpected positional argument"
+                };
+                errors.push(ArgError::new(flag, reason));
+                continue;
+            };
+
+            if let FlagRule::Switch = rule {
+                match inline_value {
+                    Some(_) => errors.push(ArgError::new(flag, "flag does not take a value")),
+                    None => accepted.push(flag.to_owned()),
+                }
+                continue;
+            }
+
+            let Some(value) = inline_value.or_else(|| iter.next().map(String::as_str)) else {
+                errors.push(ArgError::new(flag, "missing value"));
+                continue;
+            };
+            match rule.check(flag, value, output_root) {
+                Ok(value) => {
+                    accepted.push(flag.to_owned());
+                    accepted.push(value);
+                },
+                Err(err) => errors.push(err),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(accepted)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn output_root() -> PathBuf {
+        std::env::temp_dir().join("frostflake-scans").join("1")
+    }
+
+    #[test]
+    fn splits_quoted_arguments() {
+        assert_eq!(
+            split_argv("  --min-star 4\t--dump ").unwrap(),
+            args(&["--min-star", "4", "--dump"])
+        );
+        assert_eq!(
+            split_argv(r#"--window "原神 Genshin" --output-dir 'out dir'"#).unwrap(),
+            args(&["--window", "原神 Genshin", "--output-dir", "out dir"])
+        );
+        // 引号可以出现在参数中间，空引号是一个空参数
+        assert_eq!(
+            split_argv(r#"--window=a"b c"d """#).unwrap(),
+            args(&["--window=ab cd", ""])
+        );
+        assert_eq!(
+            split_argv(r#"'say "hi"' "it's""#).unwrap(),
+            args(&[r#"say "hi""#, "it's"])
+        );
+        // 反斜杠不是转义符
+        assert_eq!(
+            split_argv(r#"--output-dir C:\Users\a\ "D:\b\""#).unwrap(),
+            args(&["--output-dir", r"C:\Users\a\", r"D:\b\"])
+        );
+        assert!(split_argv("").unwrap().is_empty());
+
+        let err = split_argv(r#"--window "Genshin"#).unwrap_err();
+        assert_eq!(err.arg, "Genshin");
+        assert!(err.reason.contains("unterminated"));
+        assert!(split_argv("--window 'a").is_err());
+    }
+
+    #[test]
+    fn checks_values_against_rules() {
+        let root = output_root();
+        let int = FlagRule::Int { min: -2, max: 5 };
+        assert!(int.check("--n", "-2", &root).is_ok());
+        assert!(int.check("--n", "5", &root).is_ok());
+        for value in ["6", "-3", "1.5", "", "five", "99999999999999999999"] {
+            assert!(int.check("--n", value, &root).is_err(), "{value}");
+        }
+
+        let float = FlagRule::Float { min: 0.5, max: 2.0 };
+        assert!(float.check("--f", "0.5", &root).is_ok());
+        assert!(float.check("--f", "2", &root).is_ok());
+        for value in ["0.49", "2.01", "NaN", "inf", "x"] {
+            assert!(float.check("--f", value, &root).is_err(), "{value}");
+        }
+
+        let choice = FlagRule::Choice {
+            values: args(&["mona", "good"]),
+        };
+        assert_eq!(choice.check("--c", "good", &root).unwrap(), "good");
+        assert!(choice.check("--c", "Mona", &root).is_err());
+        assert!(choice.check("--c", "", &root).is_err());
+
+        let text = FlagRule::Text { max_len: 3 };
+        assert!(text.check("--t", "原神启", &root).is_ok());
+        let err = text.check("--t", "原神启动", &root).unwrap_err();
+        assert_eq!(err.arg, "--t");
+    }
+
+    #[test]
+    fn resolves_paths_inside_the_root() {
+        let root = output_root();
+        assert_eq!(resolve_inside(&root, "out"), Some(root.join("out")));
+        assert_eq!(resolve_inside(&root, "./a/../b/./c"), Some(root.join("b").join("c")));
+        assert_eq!(resolve_inside(&root, "."), Some(root.clone()));
+        assert_eq!(
+            resolve_inside(&root, root.join("out").to_str().unwrap()),
+            Some(root.join("out"))
+        );
+
+        // 字面上的 `..` 不能离开根目录，也不能借前缀相同的兄弟目录绕过
+        assert_eq!(resolve_inside(&root, ".."), None);
+        assert_eq!(resolve_inside(&root, "../2"), None);
+        assert_eq!(resolve_inside(&root, "a/../../1-evil"), None);
+        assert_eq!(resolve_inside(&root, "../../../../../../../../../../etc"), None);
+        assert_eq!(resolve_inside(&root, root.parent().unwrap().to_str().unwrap()), None);
+        assert_eq!(resolve_inside(&root, std::env::temp_dir().to_str().unwrap()), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn rejects_windows_specific_paths() {
+        let root = PathBuf::from(r"C:\Users\a\AppData\Local\frostflake-rs\scans\1");
+        assert_eq!(resolve_inside(&root, "out"), Some(root.join("out")));
+        // 盘符相对路径、根相对路径与网络路径都不在根目录之内
+        for value in [r"C:out", r"D:out", r"\Windows", r"\\server\share\out", r"C:\Windows"] {
+            assert_eq!(resolve_inside(&root, value), None, "{value}");
+        }
+        assert_eq!(resolve_inside(&root, r"..\2"), None);
+    }
+
+    #[test]
+    fn validates_arguments() {
+        let policy = YasArgsPolicy::default();
+        let root = output_root();
+        let accepted = policy
+            .validate(
+                &args(&[
+                    "--min-star=4",
+                    "--dump",
+                    "--output-format",
+                    "good",
+                    "--output-dir",
+                    "out",
+                ]),
+                &root,
+            )
+            .unwrap();
+        let output_dir = root.join("out").display().to_string();
+        assert_eq!(
+            accepted,
+            args(&[
+                "--min-star",
+                "4",
+                "--dump",
+                "--output-format",
+                "good",
+                "--output-dir",
+                &output_dir
+            ])
+        );
+
+        let outside = std::env::temp_dir().display().to_string();
+        let errors = policy
+            .validate(
+                &args(&[
+                    "--output-dir",
+                    "../2",
+                    "--output-dir",
+                    &outside,
+                    "--exec",
+                    "calc",
+                    "-x",
+                    "--min-star",
+                    "6",
+                    "--dump=1",
+                    "--speed",
+                ]),
+                &root,
+            )
+            .unwrap_err();
+        let rejected: Vec<_> = errors
+            .iter()
+            .map(|err| (err.arg.as_str(), err.reason.as_str()))
+            .collect();
+        assert_eq!(rejected[0].0, "--output-dir");
+        assert_eq!(rejected[1].0, "--output-dir");
+        assert_eq!(rejected[2], ("--exec", "flag is not allowed"));
+        // `--exec` 的值被当作位置参数拒绝
+        assert_eq!(rejected[3], ("calc", "unexpected positional argument"));
+        assert_eq!(rejected[4], ("-x", "flag is not allowed"));
+        assert_eq!(rejected[5].0, "--min-star");
+        assert_eq!(rejected[6], ("--dump", "flag does not take a value"));
+        assert_eq!(rejected[7], ("--speed", "missing value"));
+        assert_eq!(errors.len(), 8);
+    }
+
+    #[test]
+    fn loads_policy_for_the_yas_version() {
+        let path = std::env::temp_dir().join(format!("frostflake-yas-args-{}.json", Uuid::new_v4()));
+        assert!(YasArgsPolicy::load_from(&path, "v1.0.0")
+            .flags
+            .contains_key("--min-star"));
+
+        let profiles = r#"{
+            "v1.0.0": {"--min-star": {"type": "int", "min": 3, "max": 5}},
+            "*": {"--dump": {"type": "switch"}}
+        }"#;
+        fs::write(&path, profiles).unwrap();
+        let policy = YasArgsPolicy::load_from(&path, "v1.0.0");
+        assert_eq!(policy.flags.len(), 1);
+        assert!(policy.validate(&args(&["--min-star", "3"]), &output_root()).is_ok());
+        assert!(policy.validate(&args(&["--min-star", "2"]), &output_root()).is_err());
+        assert!(policy.validate(&args(&["--dump"]), &output_root()).is_err());
+
+        // 没有对应版本时使用 `*`
+        let policy = YasArgsPolicy::load_from(&path, "v2.0.0");
+        assert_eq!(policy.flags.len(), 1);
+        assert!(policy.validate(&args(&["--dump"]), &output_root()).is_ok());
+        assert!(policy.validate(&args(&["--min-star", "3"]), &output_root()).is_err());
+
+        // 没有 `*` 时使用内置白名单，无法解析时也是
+        fs::write(&path, r#"{"v1.0.0": {}}"#).unwrap();
+        assert!(YasArgsPolicy::load_from(&path, "v2.0.0")
+            .flags
+            .contains_key("--output-dir"));
+        fs::write(&path, "not json").unwrap();
+        assert!(YasArgsPolicy::load_from(&path, "v1.0.0")
+            .flags
+            .contains_key("--output-dir"));
+        fs::remove_file(path).unwrap();
+    }
+}